@@ -1,25 +1,862 @@
-use axum::{routing::post, Router};
+use std::io::{self, Write};
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{
+        multipart::{Field, MultipartError},
+        DefaultBodyLimit, Multipart, Query,
+    },
+    http::{Request, StatusCode},
+    response,
+    routing::post,
+    Json, Router,
+};
+use flate2::write::GzDecoder;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{
+    char::canonical_combining_class, is_nfc_quick, IsNormalized, UnicodeNormalization,
+};
 
 use crate::AppState;
 
-async fn count_elf_in_input(body: String) -> String {
-    let total_elf = body.matches("elf").count();
-    let total_shelf = body.matches("shelf").count();
-    let total_elf_in_shelf = body
-        .chars()
-        .collect::<Vec<_>>()
-        .windows("elf on a shelf".len())
-        .filter(|slice| slice.iter().collect::<String>() == "elf on a shelf")
-        .count();
+/// Largest multipart body accepted by `/6/files`.
+const MAX_UPLOAD_SIZE: usize = 32 * 1024 * 1024;
+/// Largest text a single gzipped file may expand to.
+const MAX_DECOMPRESSED_SIZE: usize = 128 * 1024 * 1024;
+/// The characters outside ASCII that Unicode case folding maps to ASCII letters: the long s
+/// and the Kelvin sign.
+const FOLDED_INTO_ASCII: [char; 2] = ['\u{17f}', '\u{212a}'];
+
+#[derive(Deserialize)]
+struct CountRequest {
+    text: String,
+    patterns: Vec<PatternQuery>,
+}
+
+#[derive(Deserialize)]
+struct PatternQuery {
+    pattern: String,
+    #[serde(default)]
+    regex: bool,
+    #[serde(default = "default_case_sensitive")]
+    case_sensitive: bool,
+    #[serde(default)]
+    overlapping: bool,
+}
+
+#[derive(Serialize)]
+struct PatternCount {
+    pattern: String,
+    count: usize,
+    offsets: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct ElfCountOptions {
+    include: Option<String>,
+    #[serde(default)]
+    fold_case: bool,
+    normalization: Option<Normalization>,
+    #[serde(default)]
+    whole_words: bool,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Normalization {
+    Nfc,
+    Nfkc,
+}
+
+/// How the text is transformed before looking for elves and shelves in it.
+#[derive(Default, Clone, Copy)]
+struct MatchOptions {
+    fold_case: bool,
+    normalization: Option<Normalization>,
+    whole_words: bool,
+}
+
+#[derive(Default, Clone, Copy)]
+struct ElfDetails {
+    positions: bool,
+    lines: bool,
+    bare_shelves: bool,
+}
+
+#[derive(Default, Serialize)]
+struct ElfCount {
+    elf: usize,
+    #[serde(rename = "elf on a shelf")]
+    elf_on_a_shelf: usize,
+    #[serde(rename = "shelf with no elf on it")]
+    shelf_with_no_elf: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    positions: Option<ElfPositions>,
+}
+
+#[derive(Serialize)]
+struct FileElfCount {
+    file: String,
+    #[serde(flatten)]
+    count: ElfCount,
+}
+
+#[derive(Serialize)]
+struct FilesElfCount {
+    files: Vec<FileElfCount>,
+    total: ElfCount,
+}
+
+#[derive(Serialize)]
+struct ElfPositions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elf: Option<Vec<Location>>,
+    #[serde(rename = "elf on a shelf", skip_serializing_if = "Option::is_none")]
+    elf_on_a_shelf: Option<Vec<Location>>,
+    #[serde(
+        rename = "shelf with no elf on it",
+        skip_serializing_if = "Option::is_none"
+    )]
+    shelf_with_no_elf: Option<Vec<Location>>,
+}
+
+#[derive(Serialize)]
+struct Location {
+    offset: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+}
+
+/// Finds the occurrences of a pattern, overlapping ones included, one byte at a time so the
+/// input can arrive in chunks of any size.
+struct StreamCounter<const N: usize> {
+    pattern: &'static [u8; N],
+    fallback: [usize; N],
+    matched: usize,
+}
+
+/// Matches completed by the last byte, dropped one by one when they are not whole words.
+#[derive(Default)]
+struct Completed {
+    elf: Option<Location>,
+    elf_on_a_shelf: Option<Location>,
+    shelf: Option<Location>,
+}
+
+/// Where a byte of the body sits, the column counting the characters before it on its line.
+#[derive(Clone, Copy)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+/// Splits bytes into characters, each invalid sequence becoming U+FFFD, along with the bytes
+/// each one was decoded from.
+struct Utf8Decoder<'a> {
+    rest: &'a [u8],
+    /// Whether an incomplete character at the end is decoded too instead of left out.
+    last: bool,
+}
+
+/// Scans the body for elves and shelves, keeping only the locations asked for.
+struct ElfScanner {
+    elf: StreamCounter<3>,
+    shelf: StreamCounter<5>,
+    elf_on_a_shelf: StreamCounter<14>,
+    matching: MatchOptions,
+    /// Position of the next byte of the body.
+    position: Position,
+    lines: bool,
+    total_elf: usize,
+    total_elf_on_a_shelf: usize,
+    total_bare_shelf: usize,
+    elf_locations: Option<Vec<Location>>,
+    elf_on_a_shelf_locations: Option<Vec<Location>>,
+    bare_shelf_locations: Option<Vec<Location>>,
+    /// Bytes not scanned yet, only buffered when the text has to be decoded.
+    pending: Vec<u8>,
+    /// Last characters seen, to check what precedes a match.
+    recent: [char; RECENT_CHARS],
+    /// Where the last characters seen come from in the body.
+    sources: [Position; RECENT_CHARS],
+    chars_seen: usize,
+    /// Matches waiting for the next character to know if they end a word.
+    unresolved: Option<Completed>,
+}
+
+const RECENT_CHARS: usize = 16;
+/// Bytes buffered at most while waiting for the end of a run of combining characters.
+const MAX_PENDING: usize = 64 * 1024;
+
+/// Feeds the decompressed text of a file to its scanner, up to `MAX_DECOMPRESSED_SIZE`.
+struct DecompressedFile {
+    scanner: ElfScanner,
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl<const N: usize> StreamCounter<N> {
+    fn new(pattern: &'static [u8; N]) -> Self {
+        // Length of the longest proper prefix of the pattern that is also a suffix of
+        // `pattern[..=i]`, as in the Knuth-Morris-Pratt algorithm.
+        let mut fallback = [0; N];
+        let mut border = 0;
+        for i in 1..N {
+            while border > 0 && pattern[i] != pattern[border] {
+                border = fallback[border - 1];
+            }
+            if pattern[i] == pattern[border] {
+                border += 1;
+            }
+            fallback[i] = border;
+        }
+
+        Self {
+            pattern,
+            fallback,
+            matched: 0,
+        }
+    }
+
+    /// Returns whether the byte completes an occurrence of the pattern.
+    fn feed(&mut self, byte: u8) -> bool {
+        while self.matched > 0 && self.pattern[self.matched] != byte {
+            self.matched = self.fallback[self.matched - 1];
+        }
+        if self.pattern[self.matched] == byte {
+            self.matched += 1;
+        }
+        if self.matched == N {
+            self.matched = self.fallback[N - 1];
+            return true;
+        }
+
+        false
+    }
+}
+
+impl ElfCountOptions {
+    fn details(&self) -> Result<ElfDetails, (StatusCode, String)> {
+        let mut details = ElfDetails::default();
+        let Some(include) = &self.include else {
+            return Ok(details);
+        };
+
+        for detail in include.split(',').map(str::trim) {
+            match detail {
+                "positions" => details.positions = true,
+                "lines" => details.lines = true,
+                "bare_shelves" => details.bare_shelves = true,
+                _ => {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        format!("The detail \"{detail}\" is not supported"),
+                    ))
+                }
+            }
+        }
+
+        if details.lines && !details.positions && !details.bare_shelves {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The detail \"lines\" needs \"positions\" or \"bare_shelves\"".into(),
+            ));
+        }
+
+        Ok(details)
+    }
+
+    fn matching(&self) -> MatchOptions {
+        MatchOptions {
+            fold_case: self.fold_case,
+            normalization: self.normalization,
+            whole_words: self.whole_words,
+        }
+    }
+}
+
+impl MatchOptions {
+    /// Whether the raw bytes can be scanned as they come, without decoding them.
+    fn is_raw(&self) -> bool {
+        !self.fold_case && self.normalization.is_none() && !self.whole_words
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Position {
+    fn advance(&mut self, byte: u8) {
+        self.offset += 1;
+        if byte == b'\n' {
+            self.line += 1;
+            self.column = 0;
+        } else if byte & 0xC0 != 0x80 {
+            // Continuation bytes of a multi-byte character do not move the column.
+            self.column += 1;
+        }
+    }
+}
+
+impl<'a> Iterator for Utf8Decoder<'a> {
+    type Item = (char, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = match *self.rest.first()? {
+            0x00..=0x7F => 1,
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => 1,
+        };
+        if len > self.rest.len() && !self.last {
+            return None;
+        }
+
+        let candidate = &self.rest[..len.min(self.rest.len())];
+        let (c, len) = match std::str::from_utf8(candidate) {
+            Ok(text) => (text.chars().next()?, len),
+            Err(e) => (
+                char::REPLACEMENT_CHARACTER,
+                e.error_len().unwrap_or(candidate.len()),
+            ),
+        };
+
+        let (bytes, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some((c, bytes))
+    }
+}
+
+impl ElfScanner {
+    fn new(details: ElfDetails, matching: MatchOptions) -> Self {
+        let locations = |enabled: bool| enabled.then(Vec::new);
+        let start = Position {
+            offset: 0,
+            line: 1,
+            column: 0,
+        };
+
+        Self {
+            elf: StreamCounter::new(b"elf"),
+            shelf: StreamCounter::new(b"shelf"),
+            elf_on_a_shelf: StreamCounter::new(b"elf on a shelf"),
+            matching,
+            position: start,
+            lines: details.lines,
+            total_elf: 0,
+            total_elf_on_a_shelf: 0,
+            total_bare_shelf: 0,
+            elf_locations: locations(details.positions),
+            elf_on_a_shelf_locations: locations(details.positions),
+            bare_shelf_locations: locations(details.bare_shelves),
+            pending: Vec::new(),
+            recent: ['\0'; RECENT_CHARS],
+            sources: [start; RECENT_CHARS],
+            chars_seen: 0,
+            unresolved: None,
+        }
+    }
+
+    fn feed_all(&mut self, bytes: &[u8]) {
+        if self.matching.is_raw() {
+            for &byte in bytes {
+                let completed = self.step(byte);
+                self.position.advance(byte);
+                self.record(completed);
+            }
+            return;
+        }
+
+        self.pending.extend_from_slice(bytes);
+        self.feed_text(false);
+    }
+
+    /// Whether nothing before `c` can compose with it, so the text can be normalized in
+    /// pieces starting there.
+    fn starts_segment(&self, c: char) -> bool {
+        let first = match self.matching.normalization {
+            Some(Normalization::Nfc) => std::iter::once(c).nfd().next(),
+            Some(Normalization::Nfkc) => std::iter::once(c).nfkd().next(),
+            None => return true,
+        };
+
+        first.is_some_and(|first| {
+            canonical_combining_class(first) == 0
+                && is_nfc_quick(std::iter::once(first)) == IsNormalized::Yes
+        })
+    }
+
+    /// Scans the pending bytes up to the last character starting a segment, which the next
+    /// bytes may still compose with, or all of them once the body is over.
+    fn feed_text(&mut self, last: bool) {
+        let pending = std::mem::take(&mut self.pending);
+
+        let mut decoded = 0;
+        let mut end = 0;
+        for (c, bytes) in (Utf8Decoder {
+            rest: &pending,
+            last,
+        }) {
+            if self.starts_segment(c) {
+                end = decoded;
+            }
+            decoded += bytes.len();
+        }
+        if last || (end == 0 && pending.len() > MAX_PENDING) {
+            end = decoded;
+        }
+
+        let mut segment = String::new();
+        let mut start = self.position;
+        for (c, bytes) in (Utf8Decoder {
+            rest: &pending[..end],
+            last: true,
+        }) {
+            if self.starts_segment(c) && !segment.is_empty() {
+                self.feed_segment(&segment, start);
+                segment.clear();
+                start = self.position;
+            }
+
+            segment.push(c);
+            bytes.iter().for_each(|&byte| self.position.advance(byte));
+        }
+        self.feed_segment(&segment, start);
+
+        self.pending = pending[end..].to_vec();
+    }
+
+    /// Scans the transformed characters of a segment, all located where it starts.
+    fn feed_segment(&mut self, segment: &str, source: Position) {
+        let normalized: Box<dyn Iterator<Item = char>> = match self.matching.normalization {
+            Some(Normalization::Nfc) => Box::new(segment.nfc()),
+            Some(Normalization::Nfkc) => Box::new(segment.nfkc()),
+            None => Box::new(segment.chars()),
+        };
+
+        for c in normalized {
+            if self.matching.fold_case {
+                c.to_lowercase().for_each(|c| self.feed_char(c, source));
+            } else {
+                self.feed_char(c, source);
+            }
+        }
+    }
+
+    fn feed_char(&mut self, c: char, source: Position) {
+        if let Some(unresolved) = self.unresolved.take() {
+            self.resolve(unresolved, Some(c));
+        }
+
+        self.sources[self.chars_seen % RECENT_CHARS] = source;
+        let mut completed = Completed::default();
+        for &byte in c.encode_utf8(&mut [0; 4]).as_bytes() {
+            let last = self.step(byte);
+            completed.elf = completed.elf.or(last.elf);
+            completed.elf_on_a_shelf = completed.elf_on_a_shelf.or(last.elf_on_a_shelf);
+            completed.shelf = completed.shelf.or(last.shelf);
+        }
+
+        if self.matching.whole_words {
+            let starts_word = |len: usize| {
+                self.chars_seen + 1 == len
+                    || !is_word_char(self.recent[(self.chars_seen - len) % RECENT_CHARS])
+            };
+            completed.elf = completed.elf.filter(|_| starts_word(3));
+            completed.elf_on_a_shelf = completed.elf_on_a_shelf.filter(|_| starts_word(14));
+            completed.shelf = completed.shelf.filter(|_| starts_word(5));
+        }
+
+        self.recent[self.chars_seen % RECENT_CHARS] = c;
+        self.chars_seen += 1;
+
+        if self.matching.whole_words {
+            self.unresolved = Some(completed);
+        } else {
+            self.record(completed);
+        }
+    }
+
+    /// Keeps the matches that are not followed by another character of the same word.
+    fn resolve(&mut self, unresolved: Completed, next: Option<char>) {
+        if !matches!(next, Some(next) if is_word_char(next)) {
+            self.record(unresolved);
+        }
+    }
+
+    fn step(&mut self, byte: u8) -> Completed {
+        Completed {
+            elf: self.elf.feed(byte).then(|| self.location(3)),
+            elf_on_a_shelf: self.elf_on_a_shelf.feed(byte).then(|| self.location(14)),
+            shelf: self.shelf.feed(byte).then(|| self.location(5)),
+        }
+    }
+
+    fn record(&mut self, completed: Completed) {
+        if let Some(location) = completed.elf {
+            self.total_elf += 1;
+            if let Some(locations) = &mut self.elf_locations {
+                locations.push(location);
+            }
+        }
+
+        // A shelf ending on the same byte as an elf on a shelf is the one the elf sits on.
+        let on_a_shelf = completed.elf_on_a_shelf.is_some();
+        if let Some(location) = completed.elf_on_a_shelf {
+            self.total_elf_on_a_shelf += 1;
+            if let Some(locations) = &mut self.elf_on_a_shelf_locations {
+                locations.push(location);
+            }
+        }
+
+        if let Some(location) = completed.shelf.filter(|_| !on_a_shelf) {
+            self.total_bare_shelf += 1;
+            if let Some(locations) = &mut self.bare_shelf_locations {
+                locations.push(location);
+            }
+        }
+    }
+
+    /// Location in the body of an ASCII match of `len` characters ending on the current byte.
+    fn location(&self, len: usize) -> Location {
+        let start = if self.matching.is_raw() {
+            Position {
+                offset: self.position.offset + 1 - len,
+                line: self.position.line,
+                column: self.position.column + 1 - len,
+            }
+        } else {
+            self.sources[(self.chars_seen + 1 - len) % RECENT_CHARS]
+        };
+
+        Location {
+            offset: start.offset,
+            line: self.lines.then_some(start.line),
+            column: self.lines.then_some(start.column + 1),
+        }
+    }
+
+    fn finish(mut self) -> ElfCount {
+        self.feed_text(true);
+        if let Some(unresolved) = self.unresolved.take() {
+            self.resolve(unresolved, None);
+        }
+
+        let has_positions = self.elf_locations.is_some() || self.bare_shelf_locations.is_some();
+
+        ElfCount {
+            elf: self.total_elf,
+            elf_on_a_shelf: self.total_elf_on_a_shelf,
+            shelf_with_no_elf: self.total_bare_shelf,
+            positions: has_positions.then_some(ElfPositions {
+                elf: self.elf_locations,
+                elf_on_a_shelf: self.elf_on_a_shelf_locations,
+                shelf_with_no_elf: self.bare_shelf_locations,
+            }),
+        }
+    }
+}
+
+impl ElfCount {
+    fn add(mut self, other: &ElfCount) -> Self {
+        self.elf += other.elf;
+        self.elf_on_a_shelf += other.elf_on_a_shelf;
+        self.shelf_with_no_elf += other.shelf_with_no_elf;
+        self
+    }
+}
+
+/// Lets the gzip decoder write the decompressed text straight into the scanner.
+impl Write for DecompressedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > self.remaining {
+            self.exceeded = true;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the decompressed file is too large",
+            ));
+        }
+
+        self.remaining -= buf.len();
+        self.scanner.feed_all(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn default_case_sensitive() -> bool {
+    true
+}
+
+/// Literal patterns of the same case sensitivity, matched together in a single scan.
+struct LiteralGroup {
+    indexes: Vec<usize>,
+    matcher: AhoCorasick,
+}
+
+impl PatternQuery {
+    /// Case insensitive literals only join a group when folding ASCII case alone finds the
+    /// same matches as the Unicode folding of regexes, that is when neither the pattern nor
+    /// the text hold a character that folds across.
+    fn is_grouped_literal(&self, text_folds_into_ascii: bool) -> bool {
+        !self.regex && (self.case_sensitive || (self.pattern.is_ascii() && !text_folds_into_ascii))
+    }
+}
+
+impl LiteralGroup {
+    fn new(
+        patterns: &[PatternQuery],
+        case_sensitive: bool,
+        text_folds_into_ascii: bool,
+    ) -> Result<Option<Self>, aho_corasick::BuildError> {
+        let indexes = patterns
+            .iter()
+            .enumerate()
+            .filter(|(_, query)| query.case_sensitive == case_sensitive)
+            .filter(|(_, query)| query.is_grouped_literal(text_folds_into_ascii))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if indexes.is_empty() {
+            return Ok(None);
+        }
+
+        let matcher = AhoCorasickBuilder::new()
+            .ascii_case_insensitive(!case_sensitive)
+            .build(indexes.iter().map(|&index| &patterns[index].pattern))?;
+
+        Ok(Some(Self { indexes, matcher }))
+    }
+
+    /// Pushes the offsets of every literal of the group, dropping the overlapping ones of
+    /// the patterns that do not allow it.
+    fn collect(&self, text: &str, patterns: &[PatternQuery], offsets: &mut [Vec<usize>]) {
+        let mut last_ends = vec![0; self.indexes.len()];
+
+        for found in self.matcher.find_overlapping_iter(text) {
+            let group_index = found.pattern().as_usize();
+            let index = self.indexes[group_index];
+
+            if !patterns[index].overlapping && found.start() < last_ends[group_index] {
+                continue;
+            }
+
+            last_ends[group_index] = found.end();
+            offsets[index].push(found.start());
+        }
+    }
+}
+
+fn regex_offsets(regex: &Regex, text: &str, overlapping: bool) -> Vec<usize> {
+    if !overlapping {
+        return regex.find_iter(text).map(|found| found.start()).collect();
+    }
+
+    let mut offsets = Vec::new();
+    let mut start = 0;
+    while let Some(found) = regex.find_at(text, start) {
+        offsets.push(found.start());
+        match text[found.start()..].chars().next() {
+            Some(next) => start = found.start() + next.len_utf8(),
+            None => break,
+        }
+    }
+
+    offsets
+}
+
+async fn count_elf_in_input(
+    Query(options): Query<ElfCountOptions>,
+    request: Request<Body>,
+) -> response::Result<Json<ElfCount>, (StatusCode, String)> {
+    let mut scanner = ElfScanner::new(options.details()?, options.matching());
+    let mut body = request.into_body();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        scanner.feed_all(&chunk);
+    }
+
+    Ok(Json(scanner.finish()))
+}
+
+/// Scans an uploaded file, decompressing it first when it starts with the gzip magic bytes.
+async fn scan_file(
+    mut field: Field<'_>,
+    details: ElfDetails,
+    matching: MatchOptions,
+) -> Result<ElfCount, (StatusCode, String)> {
+    let unreadable = |e: MultipartError| (e.status(), e.body_text());
+
+    let mut head = Vec::new();
+    while head.len() < 2 {
+        match field.chunk().await.map_err(unreadable)? {
+            Some(chunk) => head.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+
+    let mut scanner = ElfScanner::new(details, matching);
+    if !head.starts_with(&[0x1f, 0x8b]) {
+        scanner.feed_all(&head);
+        while let Some(chunk) = field.chunk().await.map_err(unreadable)? {
+            scanner.feed_all(&chunk);
+        }
+
+        return Ok(scanner.finish());
+    }
+
+    let mut decoder = GzDecoder::new(DecompressedFile {
+        scanner,
+        remaining: MAX_DECOMPRESSED_SIZE,
+        exceeded: false,
+    });
+    let invalid_gzip = |decoder: &GzDecoder<DecompressedFile>, e: io::Error| {
+        if decoder.get_ref().exceeded {
+            (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("It decompresses to more than {MAX_DECOMPRESSED_SIZE} bytes"),
+            )
+        } else {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("The file is not a valid gzip archive: {e}"),
+            )
+        }
+    };
+
+    let mut chunk = Some(Bytes::from(head));
+    while let Some(bytes) = chunk {
+        decoder
+            .write_all(&bytes)
+            .map_err(|e| invalid_gzip(&decoder, e))?;
+        chunk = field.chunk().await.map_err(unreadable)?;
+    }
+    decoder
+        .try_finish()
+        .map_err(|e| invalid_gzip(&decoder, e))?;
+
+    let file = decoder
+        .finish()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(file.scanner.finish())
+}
+
+async fn count_elf_in_files(
+    Query(options): Query<ElfCountOptions>,
+    mut multipart: Multipart,
+) -> response::Result<Json<FilesElfCount>, (StatusCode, String)> {
+    let details = options.details()?;
+    let mut files = Vec::new();
+    let matching = options.matching();
+    let mut total = ElfCount::default();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (e.status(), e.body_text()))?
+    {
+        let Some(file) = field.file_name().map(String::from) else {
+            continue;
+        };
+
+        let count = scan_file(field, details, matching)
+            .await
+            .map_err(|(status, e)| {
+                (
+                    status,
+                    format!("The file \"{file}\" could not be read: {e}"),
+                )
+            })?;
+
+        total = total.add(&count);
+        files.push(FileElfCount { file, count });
+    }
+
+    if files.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No files were uploaded".into()));
+    }
+
+    Ok(Json(FilesElfCount { files, total }))
+}
+
+/// Counts every pattern in the text. Literals are matched together, but each regex, and each
+/// case insensitive literal that ASCII folding cannot handle, still rescans the whole text.
+async fn count_patterns(
+    Json(request): Json<CountRequest>,
+) -> response::Result<Json<Vec<PatternCount>>, (StatusCode, String)> {
+    let CountRequest { text, patterns } = request;
+    if patterns.iter().any(|query| query.pattern.is_empty()) {
+        return Err((StatusCode::BAD_REQUEST, "Patterns cannot be empty".into()));
+    }
+
+    let mut offsets = vec![Vec::new(); patterns.len()];
+
+    let text_folds_into_ascii = text.contains(FOLDED_INTO_ASCII);
+    for case_sensitive in [true, false] {
+        let group = LiteralGroup::new(&patterns, case_sensitive, text_folds_into_ascii)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        if let Some(group) = group {
+            group.collect(&text, &patterns, &mut offsets);
+        }
+    }
+
+    // Regexes, and the literals the groups cannot fold, take a pass of their own over the text.
+    let others = patterns
+        .iter()
+        .enumerate()
+        .filter(|(_, query)| !query.is_grouped_literal(text_folds_into_ascii));
+    for (index, query) in others {
+        let pattern = if query.regex {
+            query.pattern.clone()
+        } else {
+            regex::escape(&query.pattern)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!query.case_sensitive)
+            .build()
+            .map_err(|e| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "The pattern \"{}\" is not a valid regex: {e}",
+                        query.pattern
+                    ),
+                )
+            })?;
+
+        offsets[index] = regex_offsets(&regex, &text, query.overlapping);
+    }
+
+    let counts = patterns
+        .into_iter()
+        .zip(offsets)
+        .map(|(query, offsets)| PatternCount {
+            pattern: query.pattern,
+            count: offsets.len(),
+            offsets,
+        })
+        .collect();
 
-    format!(
-        "{{\"elf\":{},\"elf on a shelf\":{},\"shelf with no elf on it\":{}}}",
-        total_elf,
-        total_elf_in_shelf,
-        total_shelf - total_elf_in_shelf
-    )
+    Ok(Json(counts))
 }
 
 pub fn get_hidden_elves_routes() -> Router<AppState> {
-    Router::new().route("/", post(count_elf_in_input))
+    Router::new()
+        .route("/", post(count_elf_in_input))
+        .route("/count", post(count_patterns))
+        .route(
+            "/files",
+            post(count_elf_in_files).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+        )
 }
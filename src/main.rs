@@ -8,7 +8,9 @@ use axum::{http::StatusCode, routing::get, Router};
 use cch23_demonqilin01::{
     get_cookies_recipe_routes, get_hidden_elves_routes, get_imagery_routes, get_pokemon_routes,
     get_reindeer_routes, get_sled_routes, make_santa_database_api, make_timekeeper_api, AppState,
+    PokeApi, PokemonCache, RecipeSealer,
 };
+use shuttle_secrets::SecretStore;
 use sqlx::PgPool;
 
 async fn hello_world() -> &'static str {
@@ -20,10 +22,39 @@ async fn fake_error() -> StatusCode {
 }
 
 #[shuttle_runtime::main]
-async fn main(#[shuttle_shared_db::Postgres] pool: PgPool) -> shuttle_axum::ShuttleAxum {
+async fn main(
+    #[shuttle_shared_db::Postgres] pool: PgPool,
+    #[shuttle_secrets::Secrets] secret_store: SecretStore,
+) -> shuttle_axum::ShuttleAxum {
+    let recipe_sealer = RecipeSealer::from_config(
+        secret_store.get("RECIPE_COOKIE_MODE").as_deref(),
+        secret_store.get("RECIPE_COOKIE_KEYS").as_deref(),
+        secret_store.get("RECIPE_COOKIE_ACCEPT_LEGACY").as_deref(),
+    )
+    .map_err(shuttle_runtime::CustomError::msg)?;
+
+    let pokemon_cache = PokemonCache::from_config(
+        secret_store.get("POKEMON_CACHE_CAPACITY").as_deref(),
+        secret_store.get("POKEMON_CACHE_TTL").as_deref(),
+        secret_store.get("POKEMON_FIXTURES_DIR").as_deref(),
+        secret_store.get("POKEMON_CACHE_PERSIST").as_deref(),
+        &pool,
+    )
+    .await
+    .map_err(shuttle_runtime::CustomError::msg)?;
+
+    let pokeapi = PokeApi::from_config(
+        secret_store.get("POKEAPI_BASE_URL").as_deref(),
+        secret_store.get("POKEAPI_TIMEOUT").as_deref(),
+    )
+    .map_err(shuttle_runtime::CustomError::msg)?;
+
     let state = AppState {
         pool,
         timekeeper: Arc::new(Mutex::new(HashMap::new())),
+        recipe_sealer,
+        pokemon_cache,
+        pokeapi,
     };
 
     let router = Router::new()
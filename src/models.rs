@@ -7,12 +7,17 @@ use std::{
 use axum::extract::FromRef;
 use sqlx::PgPool;
 
+use crate::{PokeApi, PokemonCache, RecipeSealer};
+
 pub type Timekeeper = Arc<Mutex<HashMap<String, Instant>>>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
     pub timekeeper: Timekeeper,
+    pub recipe_sealer: RecipeSealer,
+    pub pokemon_cache: PokemonCache,
+    pub pokeapi: PokeApi,
 }
 
 impl FromRef<AppState> for Timekeeper {
@@ -26,3 +31,21 @@ impl FromRef<AppState> for PgPool {
         app_state.pool.clone()
     }
 }
+
+impl FromRef<AppState> for RecipeSealer {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.recipe_sealer.clone()
+    }
+}
+
+impl FromRef<AppState> for PokemonCache {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.pokemon_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for PokeApi {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.pokeapi.clone()
+    }
+}
@@ -1,107 +1,416 @@
-use axum::{extract::Path, http::StatusCode, routing::get, Router};
-use serde::Deserialize;
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderValue, StatusCode,
+    },
+    response::{self, IntoResponse},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::{future, stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashMap, fmt};
 
 use crate::AppState;
 
-const GRAVITATIONAL_ACCELERATION: f32 = 9.825;
+mod cache;
+mod client;
+mod drop;
+mod profile;
+mod quantities;
+
+pub use cache::PokemonCache;
+pub use client::PokeApi;
+use drop::{legacy_momentum, DropQuery};
+use profile::{PokemonComparison, PokemonProfile};
+use quantities::{Length, Mass, MomentumUnit, UnitSelection};
+
+const DEFAULT_RETRY_AFTER: &str = "1";
+const BATCH_CONCURRENCY: usize = 8;
+const MAX_BATCH_SIZE: usize = 100;
 
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 struct Pokemon {
     id: u16,
     name: String,
-    weight: Hectogram,
+    /// In hectograms, as the PokéAPI reports it.
+    weight: f64,
+    /// In decimeters, as the PokéAPI reports it.
+    height: f64,
+    types: Vec<PokemonType>,
+    stats: Vec<PokemonStat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PokemonType {
+    slot: u8,
+    #[serde(rename = "type")]
+    kind: NamedResource,
+}
+
+#[derive(Debug, Deserialize)]
+struct PokemonStat {
+    base_stat: u16,
+    stat: NamedResource,
 }
 
 #[derive(Debug, Deserialize)]
-struct Hectogram(f32);
-struct Kilogram(f32);
+struct NamedResource {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnitQuery {
+    unit: Option<String>,
+}
+
+/// A Pokémon as the PokéAPI accepts it, by National Dex id or by name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PokemonRef {
+    Id(u16),
+    Name(String),
+}
+
+#[derive(Debug, Serialize)]
+struct BatchWeight {
+    /// The entry as it was sent, even when it names no Pokémon.
+    pokemon: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ProblemDto>,
+}
 
-struct Newton {
-    mass: Kilogram,
-    acceleration: f32,
+#[derive(Debug, Clone)]
+enum PokemonError {
+    InvalidPokemon(String),
+    TooManyPokemon(usize),
+    InvalidDrop(String),
+    InvalidUnit(String),
+    NotFound(PokemonRef),
+    RateLimited(Option<String>),
+    Timeout,
+    Unreachable,
+    UpstreamStatus(u16),
+    MalformedResponse,
 }
 
-struct Joul {
-    force: Newton,
-    duration: f32,
+/// Problem details (RFC 7807) describing why a Pokémon could not be looked up.
+#[derive(Debug, Serialize)]
+struct ProblemDto {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
 }
 
 impl Pokemon {
-    fn momentum(&self, distance: f32) -> Joul {
-        let kilo_weigth = self.weight.to_kilogram();
-        let duration = (distance * 2.0 / GRAVITATIONAL_ACCELERATION).sqrt();
-        let force = Newton {
-            mass: kilo_weigth,
-            acceleration: GRAVITATIONAL_ACCELERATION,
-        };
+    fn mass(&self) -> Mass {
+        Mass::hectograms(self.weight)
+    }
 
-        Joul { force, duration }
+    fn height(&self) -> Length {
+        Length::decimeters(self.height)
     }
 }
 
-impl Hectogram {
-    fn to_kilogram(&self) -> Kilogram {
-        Kilogram(self.0 / 10.0)
+impl UnitQuery {
+    fn units(query: Result<Query<Self>, QueryRejection>) -> Result<UnitSelection, PokemonError> {
+        let Query(query) = query.map_err(|e| PokemonError::InvalidUnit(e.body_text()))?;
+
+        UnitSelection::parse(query.unit.as_deref()).map_err(PokemonError::InvalidUnit)
     }
 }
 
-impl Newton {
-    fn value(&self) -> f32 {
-        self.acceleration * self.mass.0
+impl PokemonRef {
+    /// Reads a batch entry, which may be an id or a name.
+    fn from_value(value: &serde_json::Value) -> Result<Self, PokemonError> {
+        let id = match value {
+            serde_json::Value::String(name) => return PokemonRef::Name(name.clone()).normalize(),
+            serde_json::Value::Number(id) => id.as_u64().and_then(|id| u16::try_from(id).ok()),
+            _ => None,
+        };
+
+        id.map(PokemonRef::Id)
+            .ok_or_else(|| PokemonError::InvalidPokemon(value.to_string()))
+    }
+
+    /// Lower cases names and reads numeric names as ids, so equal Pokémon share a key.
+    fn normalize(self) -> Result<Self, PokemonError> {
+        let name = match self {
+            PokemonRef::Id(_) => return Ok(self),
+            PokemonRef::Name(name) => name.trim().to_lowercase(),
+        };
+
+        if let Ok(id) = name.parse() {
+            return Ok(PokemonRef::Id(id));
+        }
+
+        let valid = !name.is_empty()
+            && name
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+        if valid {
+            Ok(PokemonRef::Name(name))
+        } else {
+            Err(PokemonError::InvalidPokemon(name))
+        }
     }
 }
 
-impl Joul {
-    fn value(&self) -> f32 {
-        self.force.value() * self.duration
+impl fmt::Display for PokemonRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PokemonRef::Id(id) => write!(f, "{id}"),
+            PokemonRef::Name(name) => f.write_str(name),
+        }
     }
 }
 
-async fn get_pokemon_weight(Path(pokemon_id): Path<u16>) -> (StatusCode, String) {
-    let url = format!("https://pokeapi.co/api/v2/pokemon/{pokemon_id}");
-    let res = reqwest::get(url).await;
-    if let Err(e) = res {
-        if e.is_request() {
-            let msg = format!("The request for pokemon {pokemon_id} failed");
-            return (StatusCode::NOT_FOUND, msg);
+impl PokemonError {
+    fn from_transport(error: reqwest::Error) -> Self {
+        println!("{error:#?}");
+        if error.is_timeout() {
+            PokemonError::Timeout
+        } else {
+            PokemonError::Unreachable
         }
+    }
 
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Something went wrong".into(),
-        );
+    fn status(&self) -> StatusCode {
+        match self {
+            PokemonError::InvalidPokemon(_)
+            | PokemonError::TooManyPokemon(_)
+            | PokemonError::InvalidDrop(_)
+            | PokemonError::InvalidUnit(_) => StatusCode::BAD_REQUEST,
+            PokemonError::NotFound(_) => StatusCode::NOT_FOUND,
+            PokemonError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            PokemonError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            PokemonError::Unreachable
+            | PokemonError::UpstreamStatus(_)
+            | PokemonError::MalformedResponse => StatusCode::BAD_GATEWAY,
+        }
     }
 
-    let pokemon = res.unwrap().json::<Pokemon>().await.unwrap();
-    let kilo_weight = pokemon.weight.to_kilogram().0;
+    fn title(&self) -> &'static str {
+        match self {
+            PokemonError::InvalidPokemon(_) => "Invalid pokemon",
+            PokemonError::TooManyPokemon(_) => "Too many pokemon",
+            PokemonError::InvalidDrop(_) => "Invalid drop",
+            PokemonError::InvalidUnit(_) => "Invalid unit",
+            PokemonError::NotFound(_) => "Pokemon not found",
+            PokemonError::RateLimited(_) => "Rate limited by the PokeAPI",
+            PokemonError::Timeout => "The PokeAPI timed out",
+            PokemonError::Unreachable => "The PokeAPI could not be reached",
+            PokemonError::UpstreamStatus(_) => "The PokeAPI failed",
+            PokemonError::MalformedResponse => "Malformed PokeAPI response",
+        }
+    }
 
-    (StatusCode::OK, kilo_weight.to_string())
-}
+    fn detail(&self) -> String {
+        match self {
+            PokemonError::InvalidPokemon(name) => {
+                format!("\"{name}\" is neither a pokemon id nor a pokemon name")
+            }
+            PokemonError::TooManyPokemon(count) => {
+                format!("{count} pokemon were requested, at most {MAX_BATCH_SIZE} are allowed")
+            }
+            PokemonError::InvalidDrop(message) | PokemonError::InvalidUnit(message) => {
+                message.clone()
+            }
+            PokemonError::NotFound(pokemon) => format!("There is no pokemon {pokemon}"),
+            PokemonError::RateLimited(_) => "Too many pokemon were requested, retry later".into(),
+            PokemonError::Timeout => "The PokeAPI did not answer in time".into(),
+            PokemonError::Unreachable => "The connection to the PokeAPI failed".into(),
+            PokemonError::UpstreamStatus(status) => {
+                format!("The PokeAPI answered with status {status}")
+            }
+            PokemonError::MalformedResponse => {
+                "The PokeAPI answer does not describe a pokemon".into()
+            }
+        }
+    }
 
-async fn get_drop_momentum(Path(pokemon_id): Path<u16>) -> (StatusCode, String) {
-    let url = format!("https://pokeapi.co/api/v2/pokemon/{pokemon_id}");
-    let res = reqwest::get(url).await;
-    if let Err(e) = res {
-        if e.is_request() {
-            let msg = format!("The request for pokemon {pokemon_id} failed");
-            return (StatusCode::NOT_FOUND, msg);
+    fn problem(&self) -> ProblemDto {
+        ProblemDto {
+            kind: "about:blank",
+            title: self.title(),
+            status: self.status().as_u16(),
+            detail: self.detail(),
         }
+    }
+}
 
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Something went wrong".into(),
+impl IntoResponse for PokemonError {
+    fn into_response(self) -> response::Response {
+        let status = self.status();
+        let problem = self.problem();
+
+        let mut res = (status, Json(problem)).into_response();
+        let headers = res.headers_mut();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
         );
+        if let PokemonError::RateLimited(retry_after) = self {
+            let retry_after = retry_after
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(HeaderValue::from_static(DEFAULT_RETRY_AFTER));
+            headers.insert(RETRY_AFTER, retry_after);
+        }
+
+        res
+    }
+}
+
+async fn fetch_pokemon(
+    cache: &PokemonCache,
+    api: &PokeApi,
+    pokemon: &PokemonRef,
+) -> Result<Pokemon, PokemonError> {
+    let body = cache
+        .lookup(&pokemon.to_string(), || async move {
+            let body = api.pokemon(pokemon).await?;
+            serde_json::from_str::<Pokemon>(&body).map_err(|_| PokemonError::MalformedResponse)?;
+
+            Ok(body)
+        })
+        .await?;
+
+    // Fixtures and persisted entries skip the check above.
+    serde_json::from_str(&body).map_err(|_| PokemonError::MalformedResponse)
+}
+
+async fn get_pokemon_weight(
+    State(cache): State<PokemonCache>,
+    State(api): State<PokeApi>,
+    Path(pokemon): Path<String>,
+    query: Result<Query<UnitQuery>, QueryRejection>,
+) -> response::Result<String, PokemonError> {
+    let units = UnitQuery::units(query)?;
+    let pokemon = PokemonRef::Name(pokemon).normalize()?;
+    let pokemon = fetch_pokemon(&cache, &api, &pokemon).await?;
+
+    Ok(pokemon.mass().in_unit(units.mass).to_string())
+}
+
+/// Answers with the bare impact momentum for the original 10 m drop on Earth, and with the
+/// whole outcome as JSON once the drop is customized or `format=json` is asked for.
+async fn get_drop_momentum(
+    State(cache): State<PokemonCache>,
+    State(api): State<PokeApi>,
+    Path(pokemon): Path<String>,
+    query: Result<Query<DropQuery>, QueryRejection>,
+) -> response::Result<response::Response, PokemonError> {
+    let Query(query) = query.map_err(|e| PokemonError::InvalidDrop(e.body_text()))?;
+    let units = query.units()?;
+    let pokemon = PokemonRef::Name(pokemon).normalize()?;
+    let pokemon = fetch_pokemon(&cache, &api, &pokemon).await?;
+
+    let outcome = query.simulate(pokemon.mass())?;
+
+    if query.wants_report() {
+        return Ok(Json(outcome.report(units)).into_response());
     }
 
-    let pokemon = res.unwrap().json::<Pokemon>().await.unwrap();
-    let momentum = pokemon.momentum(10.0).value();
+    let momentum = match units.momentum {
+        MomentumUnit::NS => legacy_momentum(pokemon.weight as f32).to_string(),
+        unit => outcome.momentum().in_unit(unit).to_string(),
+    };
+    Ok(momentum.into_response())
+}
+
+async fn get_pokemon_profile(
+    State(cache): State<PokemonCache>,
+    State(api): State<PokeApi>,
+    Path(pokemon): Path<String>,
+    query: Result<Query<UnitQuery>, QueryRejection>,
+) -> response::Result<Json<PokemonProfile>, PokemonError> {
+    let units = UnitQuery::units(query)?;
+    let pokemon = PokemonRef::Name(pokemon).normalize()?;
+    let pokemon = fetch_pokemon(&cache, &api, &pokemon).await?;
+
+    Ok(Json(PokemonProfile::new(pokemon, units)?))
+}
+
+async fn get_pokemon_comparison(
+    State(cache): State<PokemonCache>,
+    State(api): State<PokeApi>,
+    Path((first, second)): Path<(String, String)>,
+    query: Result<Query<UnitQuery>, QueryRejection>,
+) -> response::Result<Json<PokemonComparison>, PokemonError> {
+    let units = UnitQuery::units(query)?;
+    let first = PokemonRef::Name(first).normalize()?;
+    let second = PokemonRef::Name(second).normalize()?;
+    let (first, second) = future::try_join(
+        fetch_pokemon(&cache, &api, &first),
+        fetch_pokemon(&cache, &api, &second),
+    )
+    .await?;
+
+    let first = PokemonProfile::new(first, units)?;
+    let second = PokemonProfile::new(second, units)?;
+    Ok(Json(PokemonComparison::new(first, second)))
+}
+
+/// Looks up every distinct Pokémon once, a few at a time, answering in the order asked.
+async fn get_pokemon_weights(
+    State(cache): State<PokemonCache>,
+    State(api): State<PokeApi>,
+    query: Result<Query<UnitQuery>, QueryRejection>,
+    Json(pokemon): Json<Vec<serde_json::Value>>,
+) -> response::Result<Json<Vec<BatchWeight>>, PokemonError> {
+    let units = UnitQuery::units(query)?;
+    if pokemon.len() > MAX_BATCH_SIZE {
+        return Err(PokemonError::TooManyPokemon(pokemon.len()));
+    }
+
+    let normalized = pokemon
+        .iter()
+        .map(PokemonRef::from_value)
+        .collect::<Vec<_>>();
+    let mut distinct = normalized.iter().flatten().cloned().collect::<Vec<_>>();
+    distinct.sort_by_key(ToString::to_string);
+    distinct.dedup();
+
+    let weights = stream::iter(distinct)
+        .map(|pokemon| {
+            let (cache, api) = (&cache, &api);
+            async move {
+                let weight = fetch_pokemon(cache, api, &pokemon)
+                    .await
+                    .map(|found| found.mass().in_unit(units.mass));
+                (pokemon, weight)
+            }
+        })
+        .buffer_unordered(BATCH_CONCURRENCY)
+        .collect::<HashMap<_, _>>()
+        .await;
+
+    let results = pokemon
+        .into_iter()
+        .zip(normalized)
+        .map(|(pokemon, normalized)| {
+            let weight = normalized.and_then(|normalized| weights[&normalized].clone());
+            BatchWeight {
+                pokemon,
+                weight: weight.as_ref().ok().copied(),
+                error: weight.err().map(|e| e.problem()),
+            }
+        })
+        .collect();
 
-    (StatusCode::OK, momentum.to_string())
+    Ok(Json(results))
 }
 
 pub fn get_pokemon_routes() -> Router<AppState> {
     Router::new()
         .route("/weight/:pokemon_id", get(get_pokemon_weight))
+        .route("/weights", post(get_pokemon_weights))
         .route("/drop/:pokemon_id", get(get_drop_momentum))
+        .route("/pokemon/:pokemon_id", get(get_pokemon_profile))
+        .route("/compare/:first/:second", get(get_pokemon_comparison))
 }
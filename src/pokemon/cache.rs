@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use sqlx::{PgPool, Row};
+
+const DEFAULT_CAPACITY: usize = 256;
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct CacheEntry {
+    body: String,
+    stored_at: Instant,
+    used: u64,
+}
+
+/// Fixture files are named after the Pokémon id, and found by name through `names`.
+struct Fixtures {
+    dir: PathBuf,
+    names: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct FixtureName {
+    name: String,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    tick: u64,
+    entries: HashMap<String, CacheEntry>,
+    /// The keys by the tick they were last used at, least recently used first.
+    recency: BTreeMap<u64, String>,
+}
+
+struct CacheConfig {
+    capacity: usize,
+    ttl: Duration,
+    fixtures: Option<Fixtures>,
+    store: Option<PgPool>,
+    entries: Mutex<CacheEntries>,
+}
+
+/// Keeps the PokéAPI responses around, evicting the least recently used one when full.
+/// Fixture files are served before anything else, so the routes also work offline.
+#[derive(Clone)]
+pub struct PokemonCache(Arc<CacheConfig>);
+
+impl CacheEntries {
+    fn get(&mut self, key: &str, ttl: Duration) -> Option<String> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.used);
+        if entry.stored_at.elapsed() >= ttl {
+            self.entries.remove(key);
+            return None;
+        }
+
+        entry.used = tick;
+        self.recency.insert(tick, key.to_string());
+        Some(entry.body.clone())
+    }
+
+    fn insert(&mut self, key: &str, body: String, stored_at: Instant, capacity: usize) {
+        self.tick += 1;
+        if let Some(previous) = self.entries.remove(key) {
+            self.recency.remove(&previous.used);
+        } else if self.entries.len() >= capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        if capacity > 0 {
+            let entry = CacheEntry {
+                body,
+                stored_at,
+                used: self.tick,
+            };
+            self.entries.insert(key.to_string(), entry);
+            self.recency.insert(self.tick, key.to_string());
+        }
+    }
+}
+
+impl Fixtures {
+    /// Indexes the `<id>.json` files in `dir` by the name inside them.
+    fn index(dir: PathBuf) -> Result<Self, String> {
+        let unreadable = |e| {
+            format!(
+                "The pokemon fixtures in {} could not be read: {e}",
+                dir.display()
+            )
+        };
+        let mut names = HashMap::new();
+        for entry in std::fs::read_dir(&dir).map_err(unreadable)? {
+            let path = entry.map_err(unreadable)?.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+
+            let fixture = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|body| serde_json::from_str::<FixtureName>(&body).ok());
+            if let Some(fixture) = fixture {
+                names.insert(fixture.name.to_lowercase(), id.to_string());
+            }
+        }
+
+        Ok(Self { dir, names })
+    }
+
+    fn read(&self, key: &str) -> Option<String> {
+        let valid = key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+        if !valid {
+            return None;
+        }
+
+        let file = self.names.get(key).map_or(key, String::as_str);
+        std::fs::read_to_string(self.dir.join(format!("{file}.json"))).ok()
+    }
+}
+
+impl PokemonCache {
+    /// Cache kept in memory only, with the default size and lifetime.
+    pub fn in_memory() -> Self {
+        Self(Arc::new(CacheConfig {
+            capacity: DEFAULT_CAPACITY,
+            ttl: DEFAULT_TTL,
+            fixtures: None,
+            store: None,
+            entries: Mutex::default(),
+        }))
+    }
+
+    /// Builds the cache from the `POKEMON_CACHE_CAPACITY` (entries), `POKEMON_CACHE_TTL`
+    /// (seconds), `POKEMON_FIXTURES_DIR` (`<id>.json` files, also found by name) and `POKEMON_CACHE_PERSIST`
+    /// settings. Persisted entries are kept in the `pokemon_cache` table.
+    pub async fn from_config(
+        capacity: Option<&str>,
+        ttl: Option<&str>,
+        fixtures: Option<&str>,
+        persist: Option<&str>,
+        pool: &PgPool,
+    ) -> Result<Self, String> {
+        let capacity = match capacity.map(str::trim) {
+            None | Some("") => DEFAULT_CAPACITY,
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("\"{value}\" is not a valid cache capacity"))?,
+        };
+
+        let ttl = match ttl.map(str::trim) {
+            None | Some("") => DEFAULT_TTL,
+            Some(value) => value
+                .parse()
+                .map(Duration::from_secs)
+                .map_err(|_| format!("\"{value}\" is not a valid number of seconds"))?,
+        };
+
+        let fixtures = fixtures
+            .map(str::trim)
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Fixtures::index(PathBuf::from(dir)))
+            .transpose()?;
+
+        let persist = match persist.map(str::trim) {
+            None | Some("") => false,
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("\"{value}\" is not a valid boolean"))?,
+        };
+
+        let store = if persist {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS pokemon_cache (key VARCHAR(50) PRIMARY KEY,\
+                     body TEXT NOT NULL,stored_at TIMESTAMPTZ NOT NULL DEFAULT NOW())",
+            )
+            .execute(pool)
+            .await
+            .map_err(|e| format!("The pokemon cache table could not be created: {e}"))?;
+
+            Some(pool.clone())
+        } else {
+            None
+        };
+
+        Ok(Self(Arc::new(CacheConfig {
+            capacity,
+            ttl,
+            fixtures,
+            store,
+            entries: Mutex::default(),
+        })))
+    }
+
+    /// Returns the cached body for `key`, calling `fetch` only when neither the memory, the
+    /// fixtures nor the database have a fresh copy.
+    pub(super) async fn lookup<F, Fut, E>(&self, key: &str, fetch: F) -> Result<String, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+    {
+        let config = &self.0;
+        if let Some(body) = config.entries.lock().unwrap().get(key, config.ttl) {
+            return Ok(body);
+        }
+
+        if let Some(body) = config
+            .fixtures
+            .as_ref()
+            .and_then(|fixtures| fixtures.read(key))
+        {
+            self.remember(key, body.clone(), Instant::now());
+            return Ok(body);
+        }
+
+        if let Some((body, age)) = self.load(key).await {
+            if let Some(stored_at) = Instant::now().checked_sub(age) {
+                self.remember(key, body.clone(), stored_at);
+            }
+            return Ok(body);
+        }
+
+        let body = fetch().await?;
+        self.remember(key, body.clone(), Instant::now());
+        self.save(key, &body).await;
+
+        Ok(body)
+    }
+
+    fn remember(&self, key: &str, body: String, stored_at: Instant) {
+        let config = &self.0;
+        config
+            .entries
+            .lock()
+            .unwrap()
+            .insert(key, body, stored_at, config.capacity);
+    }
+
+    /// Reads a persisted entry still within its lifetime, along with its age.
+    async fn load(&self, key: &str) -> Option<(String, Duration)> {
+        let pool = self.0.store.as_ref()?;
+        let row = sqlx::query(
+            "SELECT body, EXTRACT(EPOCH FROM NOW() - stored_at)::FLOAT8 AS age \
+             FROM pokemon_cache WHERE key = $1",
+        )
+        .bind(key)
+        .fetch_optional(pool)
+        .await;
+
+        let row = match row {
+            Ok(row) => row?,
+            Err(e) => {
+                println!("Failed to read the pokemon cache: {e}");
+                return None;
+            }
+        };
+
+        let body: String = row.try_get("body").ok()?;
+        let age = Duration::from_secs_f64(row.try_get::<f64, _>("age").ok()?.max(0.0));
+
+        (age < self.0.ttl).then_some((body, age))
+    }
+
+    async fn save(&self, key: &str, body: &str) {
+        let Some(pool) = &self.0.store else {
+            return;
+        };
+
+        let result = sqlx::query(
+            "INSERT INTO pokemon_cache(key, body) VALUES($1, $2) \
+             ON CONFLICT (key) DO UPDATE SET body = $2, stored_at = NOW()",
+        )
+        .bind(key)
+        .bind(body)
+        .execute(pool)
+        .await;
+
+        if let Err(e) = result {
+            println!("Failed to persist the pokemon cache: {e}");
+        }
+    }
+}
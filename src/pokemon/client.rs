@@ -0,0 +1,86 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{header::RETRY_AFTER, Client, StatusCode, Url};
+
+use super::{PokemonError, PokemonRef};
+
+const DEFAULT_BASE_URL: &str = "https://pokeapi.co/api/v2";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP client for the PokéAPI, shared so connections are pooled between requests.
+#[derive(Clone)]
+pub struct PokeApi {
+    client: Client,
+    base_url: Arc<str>,
+}
+
+fn build_client(timeout: Duration) -> Result<Client, reqwest::Error> {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(timeout)
+        .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .build()
+}
+
+impl PokeApi {
+    /// Uses the given client against another API root, such as a mock server in tests.
+    pub fn new(client: Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').into(),
+        }
+    }
+
+    /// Builds the client from the `POKEAPI_BASE_URL` and `POKEAPI_TIMEOUT` (seconds) settings.
+    pub fn from_config(base_url: Option<&str>, timeout: Option<&str>) -> Result<Self, String> {
+        let base_url = match base_url.map(str::trim) {
+            None | Some("") => DEFAULT_BASE_URL,
+            Some(url) => {
+                Url::parse(url).map_err(|_| format!("\"{url}\" is not a valid PokéAPI URL"))?;
+                url
+            }
+        };
+
+        let timeout = match timeout.map(str::trim) {
+            None | Some("") => DEFAULT_TIMEOUT,
+            Some(value) => value
+                .parse()
+                .map(Duration::from_secs_f64)
+                .map_err(|_| format!("\"{value}\" is not a valid number of seconds"))?,
+        };
+
+        let client = build_client(timeout)
+            .map_err(|e| format!("The PokéAPI client could not be built: {e}"))?;
+
+        Ok(Self::new(client, base_url))
+    }
+
+    /// Fetches the raw JSON of a Pokémon, telling apart the ways the PokéAPI can fail.
+    pub(super) async fn pokemon(&self, pokemon: &PokemonRef) -> Result<String, PokemonError> {
+        let res = self
+            .client
+            .get(format!("{}/pokemon/{pokemon}", self.base_url))
+            .send()
+            .await
+            .map_err(PokemonError::from_transport)?;
+
+        match res.status() {
+            status if status.is_success() => {}
+            StatusCode::NOT_FOUND => return Err(PokemonError::NotFound(pokemon.clone())),
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                return Err(PokemonError::RateLimited(retry_after));
+            }
+            status => return Err(PokemonError::UpstreamStatus(status.as_u16())),
+        }
+
+        res.text().await.map_err(PokemonError::from_transport)
+    }
+}
@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    quantities::{
+        Acceleration, Energy, EnergyUnit, Length, Mass, MassUnit, Momentum, MomentumUnit, Time,
+        UnitSelection, Velocity, VelocityUnit,
+    },
+    PokemonError,
+};
+
+/// Gravity of the original drop, kept so `/8/drop` answers exactly as before.
+const EARTH_GRAVITY: f64 = 9.825;
+const MOON_GRAVITY: f64 = 1.62;
+const MARS_GRAVITY: f64 = 3.721;
+const DEFAULT_HEIGHT: f64 = 10.0;
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct DropQuery {
+    height: Option<f64>,
+    gravity: Option<String>,
+    #[serde(default)]
+    drag: DragModel,
+    drag_coefficient: Option<f64>,
+    unit: Option<String>,
+    #[serde(default)]
+    format: DropFormat,
+}
+
+/// How `/8/drop` answers when the drop itself is the original one.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum DropFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Air resistance, as a force of `drag_coefficient * v` or `drag_coefficient * v²`.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum DragModel {
+    #[default]
+    None,
+    Linear,
+    Quadratic,
+}
+
+#[derive(Debug)]
+pub(super) struct DropOutcome {
+    mass: Mass,
+    height: Length,
+    gravity: Acceleration,
+    drag: DragModel,
+    drag_coefficient: Option<f64>,
+    duration: Time,
+    velocity: Velocity,
+    momentum: Momentum,
+    kinetic_energy: Energy,
+}
+
+/// The outcome with every quantity expressed in the units picked with `?unit=`.
+#[derive(Debug, Serialize)]
+pub(super) struct DropReport {
+    mass: f64,
+    height: f64,
+    gravity: f64,
+    drag: DragModel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drag_coefficient: Option<f64>,
+    duration: f64,
+    velocity: f64,
+    momentum: f64,
+    kinetic_energy: f64,
+    units: ReportUnits,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportUnits {
+    mass: MassUnit,
+    height: &'static str,
+    gravity: &'static str,
+    duration: &'static str,
+    velocity: VelocityUnit,
+    momentum: MomentumUnit,
+    kinetic_energy: EnergyUnit,
+}
+
+fn invalid(message: impl Into<String>) -> PokemonError {
+    PokemonError::InvalidDrop(message.into())
+}
+
+fn parse_gravity(gravity: Option<&str>) -> Result<Acceleration, PokemonError> {
+    let gravity = match gravity.map(str::trim) {
+        None | Some("") => return Ok(Acceleration::meters_per_second_squared(EARTH_GRAVITY)),
+        Some(gravity) => gravity.to_lowercase(),
+    };
+
+    let value = match gravity.as_str() {
+        "earth" => EARTH_GRAVITY,
+        "moon" => MOON_GRAVITY,
+        "mars" => MARS_GRAVITY,
+        _ => match gravity.parse::<f64>() {
+            Ok(value) if value.is_finite() && value > 0.0 => value,
+            _ => {
+                return Err(invalid(format!(
+                    "\"{gravity}\" is neither earth, moon, mars nor a positive acceleration"
+                )))
+            }
+        },
+    };
+
+    Ok(Acceleration::meters_per_second_squared(value))
+}
+
+impl DropQuery {
+    /// Whether the caller asked for anything else than the original 10 m drop on Earth.
+    fn is_customized(&self) -> bool {
+        self.height.is_some()
+            || self.gravity.is_some()
+            || self.drag != DragModel::None
+            || self.drag_coefficient.is_some()
+    }
+
+    /// Whether to answer with the whole outcome rather than the bare momentum.
+    pub(super) fn wants_report(&self) -> bool {
+        self.format == DropFormat::Json || self.is_customized()
+    }
+
+    pub(super) fn units(&self) -> Result<UnitSelection, PokemonError> {
+        UnitSelection::parse(self.unit.as_deref()).map_err(PokemonError::InvalidUnit)
+    }
+
+    /// Drops `mass` from rest and describes the impact.
+    pub(super) fn simulate(&self, mass: Mass) -> Result<DropOutcome, PokemonError> {
+        let height = self.height.unwrap_or(DEFAULT_HEIGHT);
+        if !height.is_finite() || height < 0.0 {
+            return Err(invalid("The height must be a positive number of meters"));
+        }
+        let height = Length::meters(height);
+
+        let gravity = parse_gravity(self.gravity.as_deref())?;
+
+        let drag_coefficient = match (self.drag, self.drag_coefficient) {
+            (DragModel::None, None) => None,
+            (DragModel::None, Some(_)) => {
+                return Err(invalid("A drag coefficient needs a drag model"));
+            }
+            (_, Some(coefficient)) if coefficient.is_finite() && coefficient > 0.0 => {
+                Some(coefficient)
+            }
+            (_, _) => return Err(invalid("The drag coefficient must be a positive number")),
+        };
+
+        let (duration, velocity) = match (self.drag, drag_coefficient) {
+            (DragModel::Linear, Some(b)) if mass.si() > 0.0 => {
+                linear_drop(mass, height, gravity, b)
+            }
+            (DragModel::Quadratic, Some(c)) if mass.si() > 0.0 => {
+                quadratic_drop(mass, height, gravity, c)
+            }
+            _ => {
+                let duration = Time::seconds((height.si() * 2.0 / gravity.si()).sqrt());
+                (duration, gravity * duration)
+            }
+        };
+
+        Ok(DropOutcome {
+            mass,
+            height,
+            gravity,
+            drag: self.drag,
+            drag_coefficient,
+            duration,
+            velocity,
+            momentum: mass * velocity,
+            kinetic_energy: mass.kinetic_energy(velocity),
+        })
+    }
+}
+
+impl DropOutcome {
+    pub(super) fn momentum(&self) -> Momentum {
+        self.momentum
+    }
+
+    pub(super) fn report(&self, units: UnitSelection) -> DropReport {
+        DropReport {
+            mass: self.mass.in_unit(units.mass),
+            height: self.height.si(),
+            gravity: self.gravity.si(),
+            drag: self.drag,
+            drag_coefficient: self.drag_coefficient,
+            duration: self.duration.si(),
+            velocity: self.velocity.in_unit(units.velocity),
+            momentum: self.momentum.in_unit(units.momentum),
+            kinetic_energy: self.kinetic_energy.in_unit(units.energy),
+            units: ReportUnits {
+                mass: units.mass,
+                height: "m",
+                gravity: "m_s2",
+                duration: "s",
+                velocity: units.velocity,
+                momentum: units.momentum,
+                kinetic_energy: units.energy,
+            },
+        }
+    }
+}
+
+/// Momentum of the original 10 m drop on Earth, rounded step by step in `f32` as it always
+/// was so the plain text answers stay the same to the last digit.
+pub(super) fn legacy_momentum(hectograms: f32) -> f32 {
+    let mass = hectograms / 10.0;
+    let gravity = EARTH_GRAVITY as f32;
+    let duration = (DEFAULT_HEIGHT as f32 * 2.0 / gravity).sqrt();
+
+    gravity * mass * duration
+}
+
+/// Falls against a force of `b * v`. The fall distance has no inverse in closed form, so the
+/// duration is found by bisection.
+fn linear_drop(mass: Mass, height: Length, gravity: Acceleration, b: f64) -> (Time, Velocity) {
+    let (height, gravity) = (height.si(), gravity.si());
+    let tau = mass.si() / b;
+    let terminal = gravity * tau;
+    let fallen = |t: f64| terminal * (t - tau * (1.0 - (-t / tau).exp()));
+
+    // The body never falls faster than terminal velocity, and reaches it after about `tau`.
+    let (mut low, mut high) = (0.0, height / terminal + tau);
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if fallen(middle) < height {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    let duration = (low + high) / 2.0;
+    let velocity = terminal * (1.0 - (-duration / tau).exp());
+    (
+        Time::seconds(duration),
+        Velocity::meters_per_second(velocity),
+    )
+}
+
+/// Falls against a force of `c * v²`, which has closed forms for both speed and duration.
+fn quadratic_drop(mass: Mass, height: Length, gravity: Acceleration, c: f64) -> (Time, Velocity) {
+    let (height, gravity) = (height.si(), gravity.si());
+    let terminal = (mass.si() * gravity / c).sqrt();
+    let decay = gravity * height / (terminal * terminal);
+
+    let velocity = terminal * (1.0 - (-2.0 * decay).exp()).sqrt();
+    // acosh(e^decay), written so that long falls do not overflow.
+    let duration = terminal / gravity * (decay + (1.0 + (1.0 - (-2.0 * decay).exp()).sqrt()).ln());
+    (
+        Time::seconds(duration),
+        Velocity::meters_per_second(velocity),
+    )
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::{
+    drop::DropQuery,
+    quantities::{MassUnit, MomentumUnit, UnitSelection},
+    Pokemon, PokemonError,
+};
+
+/// A Pokémon in SI units, with what can be derived from its size.
+#[derive(Debug, Serialize)]
+pub(super) struct PokemonProfile {
+    id: u16,
+    name: String,
+    types: Vec<String>,
+    height: f64,
+    weight: f64,
+    /// Weight over height squared, in kg/m², like a body mass index.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bmi: Option<f64>,
+    /// Momentum at the end of the original 10 m drop on Earth.
+    drop_momentum: f64,
+    stats: BTreeMap<String, u16>,
+    base_stat_total: u32,
+    units: ProfileUnits,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileUnits {
+    height: &'static str,
+    weight: MassUnit,
+    bmi: &'static str,
+    drop_momentum: MomentumUnit,
+}
+
+/// Two Pokémon side by side. Differences are the first one minus the second one, and a
+/// winner is left out on a tie.
+#[derive(Debug, Serialize)]
+pub(super) struct PokemonComparison {
+    first: PokemonProfile,
+    second: PokemonProfile,
+    differences: ProfileDifferences,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    heavier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taller: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stronger: Option<String>,
+    shared_types: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileDifferences {
+    height: f64,
+    weight: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bmi: Option<f64>,
+    drop_momentum: f64,
+    stats: BTreeMap<String, i32>,
+    base_stat_total: i64,
+}
+
+impl PokemonProfile {
+    pub(super) fn new(pokemon: Pokemon, units: UnitSelection) -> Result<Self, PokemonError> {
+        let (mass, height) = (pokemon.mass(), pokemon.height());
+        let bmi = match height.si() {
+            meters if meters > 0.0 => Some(mass.si() / (meters * meters)),
+            _ => None,
+        };
+        let drop = DropQuery::default().simulate(mass)?;
+
+        let mut types = pokemon.types;
+        types.sort_by_key(|kind| kind.slot);
+        let stats = pokemon
+            .stats
+            .into_iter()
+            .map(|stat| (stat.stat.name, stat.base_stat))
+            .collect::<BTreeMap<_, _>>();
+
+        Ok(PokemonProfile {
+            id: pokemon.id,
+            name: pokemon.name,
+            types: types.into_iter().map(|kind| kind.kind.name).collect(),
+            height: height.si(),
+            weight: mass.in_unit(units.mass),
+            bmi,
+            drop_momentum: drop.momentum().in_unit(units.momentum),
+            base_stat_total: stats.values().copied().map(u32::from).sum(),
+            stats,
+            units: ProfileUnits {
+                height: "m",
+                weight: units.mass,
+                bmi: "kg_m2",
+                drop_momentum: units.momentum,
+            },
+        })
+    }
+}
+
+/// Names the Pokémon with the larger value, if they differ.
+fn winner<T: PartialOrd>(
+    first: &PokemonProfile,
+    second: &PokemonProfile,
+    by: fn(&PokemonProfile) -> T,
+) -> Option<String> {
+    let (a, b) = (by(first), by(second));
+    if a > b {
+        Some(first.name.clone())
+    } else if b > a {
+        Some(second.name.clone())
+    } else {
+        None
+    }
+}
+
+impl PokemonComparison {
+    pub(super) fn new(first: PokemonProfile, second: PokemonProfile) -> Self {
+        let stats = first
+            .stats
+            .keys()
+            .chain(second.stats.keys())
+            .map(|name| {
+                let stat = |profile: &PokemonProfile| {
+                    i32::from(profile.stats.get(name).copied().unwrap_or_default())
+                };
+                (name.clone(), stat(&first) - stat(&second))
+            })
+            .collect();
+
+        let differences = ProfileDifferences {
+            height: first.height - second.height,
+            weight: first.weight - second.weight,
+            bmi: first.bmi.zip(second.bmi).map(|(a, b)| a - b),
+            drop_momentum: first.drop_momentum - second.drop_momentum,
+            stats,
+            base_stat_total: i64::from(first.base_stat_total) - i64::from(second.base_stat_total),
+        };
+
+        let shared_types = first
+            .types
+            .iter()
+            .filter(|kind| second.types.contains(kind))
+            .cloned()
+            .collect();
+
+        PokemonComparison {
+            heavier: winner(&first, &second, |profile| profile.weight),
+            taller: winner(&first, &second, |profile| profile.height),
+            stronger: winner(&first, &second, |profile| profile.base_stat_total),
+            shared_types,
+            differences,
+            first,
+            second,
+        }
+    }
+}
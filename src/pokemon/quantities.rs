@@ -0,0 +1,260 @@
+use std::ops::Mul;
+
+use serde::Serialize;
+
+/// Declares a quantity stored in its SI unit, so only the typed conversions below can mix them.
+macro_rules! quantity {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+        pub(super) struct $name(f64);
+    };
+}
+
+quantity!(
+    /// Kilograms.
+    Mass
+);
+quantity!(
+    /// Meters.
+    Length
+);
+quantity!(
+    /// Seconds.
+    Time
+);
+quantity!(
+    /// Meters per second.
+    Velocity
+);
+quantity!(
+    /// Meters per second squared.
+    Acceleration
+);
+quantity!(
+    /// Newton seconds, or kilogram meters per second.
+    Momentum
+);
+quantity!(
+    /// Joules.
+    Energy
+);
+
+impl Mass {
+    /// The PokéAPI reports weights in hectograms.
+    pub(super) fn hectograms(value: f64) -> Self {
+        Self(value / 10.0)
+    }
+
+    pub(super) fn si(self) -> f64 {
+        self.0
+    }
+}
+
+impl Length {
+    pub(super) fn meters(value: f64) -> Self {
+        Self(value)
+    }
+
+    /// The PokéAPI reports heights in decimeters.
+    pub(super) fn decimeters(value: f64) -> Self {
+        Self(value / 10.0)
+    }
+
+    pub(super) fn si(self) -> f64 {
+        self.0
+    }
+}
+
+impl Time {
+    pub(super) fn seconds(value: f64) -> Self {
+        Self(value)
+    }
+
+    pub(super) fn si(self) -> f64 {
+        self.0
+    }
+}
+
+impl Velocity {
+    pub(super) fn meters_per_second(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl Acceleration {
+    pub(super) fn meters_per_second_squared(value: f64) -> Self {
+        Self(value)
+    }
+
+    pub(super) fn si(self) -> f64 {
+        self.0
+    }
+}
+
+impl Mul<Velocity> for Mass {
+    type Output = Momentum;
+
+    fn mul(self, velocity: Velocity) -> Momentum {
+        Momentum(self.0 * velocity.0)
+    }
+}
+
+impl Mul<Time> for Acceleration {
+    type Output = Velocity;
+
+    fn mul(self, time: Time) -> Velocity {
+        Velocity(self.0 * time.0)
+    }
+}
+
+impl Mass {
+    /// Kinetic energy of this mass moving at `velocity`, `m v² / 2`.
+    pub(super) fn kinetic_energy(self, velocity: Velocity) -> Energy {
+        Energy(self.0 * velocity.0 * velocity.0 / 2.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum MassUnit {
+    #[default]
+    Kg,
+    G,
+    Lb,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum VelocityUnit {
+    #[default]
+    MS,
+    KmH,
+    Mph,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum MomentumUnit {
+    #[default]
+    NS,
+    LbFtS,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum EnergyUnit {
+    #[default]
+    J,
+    Kj,
+    Cal,
+    Kcal,
+}
+
+const POUND: f64 = 0.453_592_37;
+const FOOT: f64 = 0.3048;
+const MILE: f64 = 1_609.344;
+const CALORIE: f64 = 4.184;
+
+impl Mass {
+    pub(super) fn in_unit(self, unit: MassUnit) -> f64 {
+        match unit {
+            MassUnit::Kg => self.0,
+            MassUnit::G => self.0 * 1_000.0,
+            MassUnit::Lb => self.0 / POUND,
+        }
+    }
+}
+
+impl Velocity {
+    pub(super) fn in_unit(self, unit: VelocityUnit) -> f64 {
+        match unit {
+            VelocityUnit::MS => self.0,
+            VelocityUnit::KmH => self.0 * 3.6,
+            VelocityUnit::Mph => self.0 * 3_600.0 / MILE,
+        }
+    }
+}
+
+impl Momentum {
+    pub(super) fn in_unit(self, unit: MomentumUnit) -> f64 {
+        match unit {
+            MomentumUnit::NS => self.0,
+            MomentumUnit::LbFtS => self.0 / (POUND * FOOT),
+        }
+    }
+}
+
+impl Energy {
+    pub(super) fn in_unit(self, unit: EnergyUnit) -> f64 {
+        match unit {
+            EnergyUnit::J => self.0,
+            EnergyUnit::Kj => self.0 / 1_000.0,
+            EnergyUnit::Cal => self.0 / CALORIE,
+            EnergyUnit::Kcal => self.0 / (CALORIE * 1_000.0),
+        }
+    }
+}
+
+/// Output units picked with `?unit=`, a comma separated list holding at most one unit of each
+/// dimension, such as `lb,kj`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub(super) struct UnitSelection {
+    pub(super) mass: MassUnit,
+    pub(super) velocity: VelocityUnit,
+    pub(super) momentum: MomentumUnit,
+    pub(super) energy: EnergyUnit,
+}
+
+impl UnitSelection {
+    pub(super) fn parse(units: Option<&str>) -> Result<Self, String> {
+        let mut selection = Self::default();
+        let mut seen = Vec::new();
+
+        let units = units.unwrap_or_default().split(',').map(str::trim);
+        for unit in units.filter(|unit| !unit.is_empty()) {
+            let dimension = match unit.to_lowercase().as_str() {
+                "kg" => selection.set_mass(MassUnit::Kg),
+                "g" => selection.set_mass(MassUnit::G),
+                "lb" => selection.set_mass(MassUnit::Lb),
+                "m_s" => selection.set_velocity(VelocityUnit::MS),
+                "km_h" => selection.set_velocity(VelocityUnit::KmH),
+                "mph" => selection.set_velocity(VelocityUnit::Mph),
+                "n_s" => selection.set_momentum(MomentumUnit::NS),
+                "lb_ft_s" => selection.set_momentum(MomentumUnit::LbFtS),
+                "j" => selection.set_energy(EnergyUnit::J),
+                "kj" => selection.set_energy(EnergyUnit::Kj),
+                "cal" => selection.set_energy(EnergyUnit::Cal),
+                "kcal" => selection.set_energy(EnergyUnit::Kcal),
+                _ => return Err(format!("\"{unit}\" is not a known unit")),
+            };
+
+            if seen.contains(&dimension) {
+                return Err(format!("Only one {dimension} unit can be picked"));
+            }
+            seen.push(dimension);
+        }
+
+        Ok(selection)
+    }
+
+    fn set_mass(&mut self, unit: MassUnit) -> &'static str {
+        self.mass = unit;
+        "mass"
+    }
+
+    fn set_velocity(&mut self, unit: VelocityUnit) -> &'static str {
+        self.velocity = unit;
+        "velocity"
+    }
+
+    fn set_momentum(&mut self, unit: MomentumUnit) -> &'static str {
+        self.momentum = unit;
+        "momentum"
+    }
+
+    fn set_energy(&mut self, unit: EnergyUnit) -> &'static str {
+        self.energy = unit;
+        "energy"
+    }
+}
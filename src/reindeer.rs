@@ -1,8 +1,19 @@
-use axum::{http::StatusCode, routing::post, Json, Router};
-use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Display},
+};
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use sqlx::PgPool;
 
 use crate::AppState;
 
+mod roster;
+mod team;
+
 #[derive(Deserialize)]
 struct Reindeer {
     strength: i32,
@@ -10,7 +21,7 @@ struct Reindeer {
     name: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
 struct DetailReindeer {
     name: String,
     strength: u32,
@@ -22,39 +33,338 @@ struct DetailReindeer {
     candies_eaten: u32,
 }
 
+/// Keys the contest response already uses for itself, which categories cannot take.
+const RESERVED_KEYS: [&str; 3] = ["winners", "unknown_fields", "contest_id"];
+
+/// Keys the upstream feeds are known to use instead of the canonical field names.
+const DEFAULT_ALIASES: [(&str, ReindeerField); 1] =
+    [("cAnD13s_3ATeN-yesT3rdAy", ReindeerField::CandiesEaten)];
+
+type RawReindeer = Map<String, Value>;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ReindeerField {
+    Name,
+    Strength,
+    Speed,
+    Height,
+    AntlerWidth,
+    SnowMagicPower,
+    FavoriteFood,
+    CandiesEaten,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum RankOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize)]
+struct ContestCategory {
+    key: String,
+    field: ReindeerField,
+    #[serde(default)]
+    order: RankOrder,
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ContestRequest {
+    Roster(Vec<RawReindeer>),
+    Custom {
+        #[serde(default)]
+        reindeer: Vec<RawReindeer>,
+        roster: Option<RosterSelection>,
+        #[serde(default = "default_categories")]
+        categories: Vec<ContestCategory>,
+        #[serde(default)]
+        aliases: HashMap<String, ReindeerField>,
+        /// Keeps the results in the contest history, which contests on the roster always do.
+        #[serde(default)]
+        record: bool,
+    },
+}
+
+/// Reindeer taken from the stored roster, either all of them or only the named ones.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RosterSelection {
+    Everyone(EveryoneKeyword),
+    Named(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EveryoneKeyword {
+    All,
+}
+
+/// Maps the keys of a raw reindeer to its fields, ignoring the letter case.
+struct ReindeerAliases(HashMap<String, ReindeerField>);
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum FieldValue {
+    Text(String),
+    Integer(u32),
+    Decimal(f32),
+}
+
 #[derive(Serialize)]
+struct CategoryWinners {
+    category: String,
+    value: FieldValue,
+    reindeer: Vec<String>,
+}
+
 struct ContestWinnersResponse {
-    fastest: String,
-    tallest: String,
-    magician: String,
-    consumer: String,
-}
-
-impl ContestWinnersResponse {
-    fn new(
-        fastest: &DetailReindeer,
-        tallest: &DetailReindeer,
-        magician: &DetailReindeer,
-        consumer: &DetailReindeer,
-    ) -> Self {
+    announcements: Vec<(String, String)>,
+    winners: Vec<CategoryWinners>,
+    unknown_fields: BTreeSet<String>,
+    contest_id: Option<i32>,
+}
+
+impl ReindeerField {
+    const ALL: [ReindeerField; 8] = [
+        ReindeerField::Name,
+        ReindeerField::Strength,
+        ReindeerField::Speed,
+        ReindeerField::Height,
+        ReindeerField::AntlerWidth,
+        ReindeerField::SnowMagicPower,
+        ReindeerField::FavoriteFood,
+        ReindeerField::CandiesEaten,
+    ];
+
+    fn key(&self) -> &'static str {
+        match self {
+            ReindeerField::Name => "name",
+            ReindeerField::Strength => "strength",
+            ReindeerField::Speed => "speed",
+            ReindeerField::Height => "height",
+            ReindeerField::AntlerWidth => "antler_width",
+            ReindeerField::SnowMagicPower => "snow_magic_power",
+            ReindeerField::FavoriteFood => "favorite_food",
+            ReindeerField::CandiesEaten => "candies_eaten",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        ReindeerField::ALL
+            .into_iter()
+            .find(|field| field.key() == key)
+    }
+}
+
+impl ReindeerAliases {
+    fn new(custom: HashMap<String, ReindeerField>) -> Self {
+        let canonical = ReindeerField::ALL
+            .into_iter()
+            .map(|field| (field.key().to_string(), field));
+        let defaults = DEFAULT_ALIASES
+            .into_iter()
+            .map(|(alias, field)| (alias.to_lowercase(), field));
+        let custom = custom
+            .into_iter()
+            .map(|(alias, field)| (alias.to_lowercase(), field));
+
+        Self(canonical.chain(defaults).chain(custom).collect())
+    }
+
+    /// Builds a reindeer from its raw keys, returning the keys that matched no field.
+    fn parse(&self, raw: RawReindeer) -> Result<(DetailReindeer, Vec<String>), String> {
+        let mut fields = Map::new();
+        let mut unknown_fields = Vec::new();
+
+        for (key, value) in raw {
+            let Some(field) = self.0.get(&key.to_lowercase()) else {
+                unknown_fields.push(key);
+                continue;
+            };
+
+            if fields.insert(field.key().to_string(), value).is_some() {
+                return Err(format!(
+                    "The field \"{}\" was given more than once",
+                    field.key()
+                ));
+            }
+        }
+
+        let reindeer = serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())?;
+
+        Ok((reindeer, unknown_fields))
+    }
+}
+
+impl DetailReindeer {
+    fn compare(&self, other: &Self, field: ReindeerField) -> Ordering {
+        match field {
+            ReindeerField::Name => self.name.cmp(&other.name),
+            ReindeerField::Strength => self.strength.cmp(&other.strength),
+            ReindeerField::Speed => self.speed.total_cmp(&other.speed),
+            ReindeerField::Height => self.height.cmp(&other.height),
+            ReindeerField::AntlerWidth => self.antler_width.cmp(&other.antler_width),
+            ReindeerField::SnowMagicPower => self.snow_magic_power.cmp(&other.snow_magic_power),
+            ReindeerField::FavoriteFood => self.favorite_food.cmp(&other.favorite_food),
+            ReindeerField::CandiesEaten => self.candies_eaten.cmp(&other.candies_eaten),
+        }
+    }
+
+    fn field_value(&self, field: ReindeerField) -> FieldValue {
+        match field {
+            ReindeerField::Name => FieldValue::Text(self.name.clone()),
+            ReindeerField::Strength => FieldValue::Integer(self.strength),
+            ReindeerField::Speed => FieldValue::Decimal(self.speed),
+            ReindeerField::Height => FieldValue::Integer(self.height),
+            ReindeerField::AntlerWidth => FieldValue::Integer(self.antler_width),
+            ReindeerField::SnowMagicPower => FieldValue::Integer(self.snow_magic_power),
+            ReindeerField::FavoriteFood => FieldValue::Text(self.favorite_food.clone()),
+            ReindeerField::CandiesEaten => FieldValue::Integer(self.candies_eaten),
+        }
+    }
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Text(text) => write!(f, "{text}"),
+            FieldValue::Integer(integer) => write!(f, "{integer}"),
+            FieldValue::Decimal(decimal) => write!(f, "{decimal}"),
+        }
+    }
+}
+
+impl ContestCategory {
+    fn new(key: &str, field: ReindeerField, message: &str) -> Self {
         Self {
-            fastest: format!(
-                "Speeding past the finish line with a strength of {} is {}",
-                fastest.strength, fastest.name
-            ),
-            tallest: format!(
-                "{} is standing tall with his {} cm wide antlers",
-                tallest.name, tallest.antler_width
-            ),
-            magician: format!(
-                "{} could blast you away with a snow magic power of {}",
-                magician.name, magician.snow_magic_power
-            ),
-            consumer: format!(
-                "{} ate lots of candies, but also some {}",
-                consumer.name, consumer.favorite_food
-            ),
+            key: key.into(),
+            field,
+            order: RankOrder::Desc,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the reindeer announced for the category along with every reindeer sharing its
+    /// value, in input order. On a tie the announced one is the last for a descending order
+    /// and the first for an ascending one, as `max_by` and `min_by` pick them.
+    fn winners<'a>(
+        &self,
+        reindeers: &'a [DetailReindeer],
+    ) -> Option<(&'a DetailReindeer, Vec<&'a DetailReindeer>)> {
+        let ranking = reindeers.iter();
+        let best = match self.order {
+            RankOrder::Desc => ranking.max_by(|a, b| a.compare(b, self.field)),
+            RankOrder::Asc => ranking.min_by(|a, b| a.compare(b, self.field)),
+        }?;
+
+        let tied = reindeers
+            .iter()
+            .filter(|reindeer| reindeer.compare(best, self.field) == Ordering::Equal)
+            .collect();
+
+        Some((best, tied))
+    }
+
+    /// Fills the placeholders of the message with the values of the winner, `{field}` standing
+    /// for the value the category ranks by. The message is read once from left to right, so
+    /// braces inside the inserted values are left alone, and so are unknown placeholders.
+    fn announce(&self, winner: &DetailReindeer) -> String {
+        let mut announcement = String::with_capacity(self.message.len());
+        let mut rest = self.message.as_str();
+
+        while let Some(start) = rest.find('{') {
+            announcement.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let field = match &rest[1..end] {
+                "field" => Some(self.field),
+                key => ReindeerField::from_key(key),
+            };
+            match field {
+                Some(field) => {
+                    announcement.push_str(&winner.field_value(field).to_string());
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    announcement.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        announcement.push_str(rest);
+        announcement
+    }
+}
+
+/// Makes sure every category gets a key of its own in the response.
+fn check_categories(categories: &[ContestCategory]) -> Result<(), String> {
+    let mut keys = BTreeSet::new();
+    for category in categories {
+        if RESERVED_KEYS.contains(&category.key.as_str()) {
+            return Err(format!(
+                "The category key \"{}\" is reserved by the response",
+                category.key
+            ));
+        }
+        if !keys.insert(category.key.as_str()) {
+            return Err(format!(
+                "The category key \"{}\" was given more than once",
+                category.key
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn default_categories() -> Vec<ContestCategory> {
+    vec![
+        ContestCategory::new(
+            "fastest",
+            ReindeerField::Speed,
+            "Speeding past the finish line with a strength of {strength} is {name}",
+        ),
+        ContestCategory::new(
+            "tallest",
+            ReindeerField::Height,
+            "{name} is standing tall with his {antler_width} cm wide antlers",
+        ),
+        ContestCategory::new(
+            "magician",
+            ReindeerField::SnowMagicPower,
+            "{name} could blast you away with a snow magic power of {snow_magic_power}",
+        ),
+        ContestCategory::new(
+            "consumer",
+            ReindeerField::CandiesEaten,
+            "{name} ate lots of candies, but also some {favorite_food}",
+        ),
+    ]
+}
+
+impl Serialize for ContestWinnersResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (key, message) in &self.announcements {
+            map.serialize_entry(key, message)?;
+        }
+        map.serialize_entry("winners", &self.winners)?;
+        if !self.unknown_fields.is_empty() {
+            map.serialize_entry("unknown_fields", &self.unknown_fields)?;
         }
+        if let Some(contest_id) = self.contest_id {
+            map.serialize_entry("contest_id", &contest_id)?;
+        }
+        map.end()
     }
 }
 
@@ -66,38 +376,115 @@ async fn get_reideers_total_strength(Json(reindeers): Json<Vec<Reindeer>>) -> St
         .to_string()
 }
 
-async fn get_contest_winners(body: String) -> axum::response::Result<String, StatusCode> {
-    let body = body.replace("cAnD13s_3ATeN-yesT3rdAy", "candies_eaten");
-    let reindeers: Vec<DetailReindeer> =
-        serde_json::from_str(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+/// Parses the reindeer sent in the request and appends the ones selected from the roster.
+async fn gather_reindeers(
+    pool: &PgPool,
+    raw_reindeers: Vec<RawReindeer>,
+    roster: Option<RosterSelection>,
+    aliases: ReindeerAliases,
+) -> axum::response::Result<(Vec<DetailReindeer>, BTreeSet<String>), (StatusCode, String)> {
+    let mut reindeers = Vec::with_capacity(raw_reindeers.len());
+    let mut unknown_fields = BTreeSet::new();
+
+    for (index, raw) in raw_reindeers.into_iter().enumerate() {
+        let (reindeer, unknown) = aliases.parse(raw).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("The reindeer at position {index} is not valid: {e}"),
+            )
+        })?;
 
-    let fastest = reindeers
-        .iter()
-        .max_by(|a, b| a.speed.total_cmp(&b.speed))
-        .expect("A reindeer fastest than others was expected");
+        reindeers.push(reindeer);
+        unknown_fields.extend(unknown);
+    }
 
-    let tallest = reindeers
-        .iter()
-        .max_by(|a, b| a.height.cmp(&b.height))
-        .expect("A reindeer tallest than others was expected");
+    match roster {
+        Some(RosterSelection::Everyone(_)) => reindeers.extend(roster::load(pool, None).await?),
+        Some(RosterSelection::Named(names)) => {
+            reindeers.extend(roster::load(pool, Some(&names)).await?)
+        }
+        None => {}
+    }
 
-    let magician = reindeers
-        .iter()
-        .max_by(|a, b| a.snow_magic_power.cmp(&b.snow_magic_power))
-        .expect("A reindeer more magician than others was expected");
+    Ok((reindeers, unknown_fields))
+}
 
-    let consumer = reindeers
-        .iter()
-        .max_by(|a, b| a.candies_eaten.cmp(&b.candies_eaten))
-        .expect("A reindeer more consumer than others was expected");
+async fn get_contest_winners(
+    State(pool): State<PgPool>,
+    body: String,
+) -> axum::response::Result<String, (StatusCode, String)> {
+    let request: ContestRequest = serde_json::from_str(&body).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            "The reindeer list does not have the correct shape".to_string(),
+        )
+    })?;
 
-    let winners = ContestWinnersResponse::new(fastest, tallest, magician, consumer);
+    let (raw_reindeers, roster, categories, aliases, record) = match request {
+        ContestRequest::Roster(reindeer) => {
+            (reindeer, None, default_categories(), HashMap::new(), false)
+        }
+        ContestRequest::Custom {
+            reindeer,
+            roster,
+            categories,
+            aliases,
+            record,
+        } => {
+            let record = record || roster.is_some();
+            (reindeer, roster, categories, aliases, record)
+        }
+    };
+    check_categories(&categories).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let (reindeers, unknown_fields) =
+        gather_reindeers(&pool, raw_reindeers, roster, ReindeerAliases::new(aliases)).await?;
+
+    if reindeers.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "At least one reindeer is needed to hold a contest".into(),
+        ));
+    }
+
+    let mut response = ContestWinnersResponse {
+        announcements: Vec::new(),
+        winners: Vec::new(),
+        unknown_fields,
+        contest_id: None,
+    };
+
+    for category in categories {
+        let Some((best, winners)) = category.winners(&reindeers) else {
+            continue;
+        };
+
+        response
+            .announcements
+            .push((category.key.clone(), category.announce(best)));
+        response.winners.push(CategoryWinners {
+            value: best.field_value(category.field),
+            reindeer: winners.iter().map(|winner| winner.name.clone()).collect(),
+            category: category.key,
+        });
+    }
+
+    // The results are only kept when the roster tables exist, a missing history must not
+    // prevent the contest from being held.
+    if record {
+        match roster::record_contest(&pool, &response.announcements, &response.winners).await {
+            Ok(contest_id) => response.contest_id = Some(contest_id),
+            Err(e) => println!("Failed record contest: {e}"),
+        }
+    }
 
-    serde_json::to_string(&winners).map_err(|_| StatusCode::BAD_REQUEST)
+    serde_json::to_string(&response).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
 pub fn get_reindeer_routes() -> Router<AppState> {
     Router::new()
         .route("/strength", post(get_reideers_total_strength))
         .route("/contest", post(get_contest_winners))
+        .route("/team", post(team::build_team))
+        .merge(roster::get_roster_routes())
 }
@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgRow, FromRow, PgPool, Row};
+
+use super::{CategoryWinners, DetailReindeer, RawReindeer, ReindeerAliases};
+use crate::AppState;
+
+type RosterResult<T> = response::Result<T, (StatusCode, String)>;
+
+#[derive(Deserialize)]
+struct ContestFilter {
+    winner: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ContestRecord {
+    id: i32,
+    held_at: DateTime<Utc>,
+    results: Vec<ContestResultRecord>,
+}
+
+#[derive(Serialize)]
+struct ContestResultRecord {
+    category: String,
+    announcement: String,
+    value: String,
+    reindeer: Vec<String>,
+}
+
+impl<'r> FromRow<'r, PgRow> for DetailReindeer {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        let unsigned = |column: &str| -> Result<u32, sqlx::Error> {
+            u32::try_from(row.try_get::<i64, _>(column)?).map_err(|e| sqlx::Error::ColumnDecode {
+                index: column.into(),
+                source: Box::new(e),
+            })
+        };
+
+        Ok(Self {
+            name: row.try_get("name")?,
+            strength: unsigned("strength")?,
+            speed: row.try_get("speed")?,
+            height: unsigned("height")?,
+            antler_width: unsigned("antler_width")?,
+            snow_magic_power: unsigned("snow_magic_power")?,
+            favorite_food: row.try_get("favorite_food")?,
+            candies_eaten: unsigned("candies_eaten")?,
+        })
+    }
+}
+
+impl DetailReindeer {
+    /// Stores the reindeer, replacing the one of the same name only when `replace` is set.
+    /// Returns whether anything was written.
+    async fn save(&self, pool: &PgPool, replace: bool) -> Result<bool, sqlx::Error> {
+        let on_conflict = if replace {
+            "DO UPDATE SET strength = $2, speed = $3, height = $4, antler_width = $5, \
+             snow_magic_power = $6, favorite_food = $7, candies_eaten = $8"
+        } else {
+            "DO NOTHING"
+        };
+
+        let result = sqlx::query(&format!(
+            "INSERT INTO reindeer(name, strength, speed, height, antler_width, snow_magic_power, \
+             favorite_food, candies_eaten) VALUES($1, $2, $3, $4, $5, $6, $7, $8) \
+             ON CONFLICT (name) {on_conflict}"
+        ))
+        .bind(&self.name)
+        .bind(i64::from(self.strength))
+        .bind(self.speed)
+        .bind(i64::from(self.height))
+        .bind(i64::from(self.antler_width))
+        .bind(i64::from(self.snow_magic_power))
+        .bind(&self.favorite_food)
+        .bind(i64::from(self.candies_eaten))
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> (StatusCode, String) {
+    println!("{error:#?}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "The reindeer roster could not be reached".into(),
+    )
+}
+
+fn parse_reindeer(raw: RawReindeer) -> RosterResult<DetailReindeer> {
+    ReindeerAliases::new(HashMap::new())
+        .parse(raw)
+        .map(|(reindeer, _)| reindeer)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// Loads the whole roster, or only the given reindeer failing when any of them is missing.
+pub(super) async fn load(
+    pool: &PgPool,
+    names: Option<&[String]>,
+) -> RosterResult<Vec<DetailReindeer>> {
+    let reindeers = match names {
+        Some(names) => sqlx::query_as::<_, DetailReindeer>(
+            "SELECT * FROM reindeer WHERE name = ANY($1) ORDER BY name",
+        )
+        .bind(names)
+        .fetch_all(pool)
+        .await
+        .map_err(database_error)?,
+        None => sqlx::query_as::<_, DetailReindeer>("SELECT * FROM reindeer ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .map_err(database_error)?,
+    };
+
+    if let Some(names) = names {
+        let missing = names
+            .iter()
+            .filter(|name| !reindeers.iter().any(|reindeer| &reindeer.name == *name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("The reindeer {missing:?} are not in the roster"),
+            ));
+        }
+    }
+
+    Ok(reindeers)
+}
+
+/// Stores the winners of a contest, returning the id the contest was recorded with.
+pub(super) async fn record_contest(
+    pool: &PgPool,
+    announcements: &[(String, String)],
+    winners: &[CategoryWinners],
+) -> Result<i32, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let contest_id: i32 = sqlx::query("INSERT INTO contests DEFAULT VALUES RETURNING id")
+        .fetch_one(&mut *transaction)
+        .await?
+        .try_get("id")?;
+
+    for (position, ((category, announcement), winners)) in
+        announcements.iter().zip(winners).enumerate()
+    {
+        sqlx::query(
+            "INSERT INTO contest_results(contest_id, position, category, announcement, value, \
+             reindeer) VALUES($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(contest_id)
+        .bind(position as i32)
+        .bind(category)
+        .bind(announcement)
+        .bind(winners.value.to_string())
+        .bind(&winners.reindeer)
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(contest_id)
+}
+
+async fn reset_roster(State(pool): State<PgPool>) -> StatusCode {
+    let queries = [
+        "DROP TABLE IF EXISTS contest_results",
+        "DROP TABLE IF EXISTS contests",
+        "DROP TABLE IF EXISTS reindeer",
+        "CREATE TABLE reindeer (name VARCHAR(50) PRIMARY KEY,strength BIGINT NOT NULL,\
+         speed REAL NOT NULL,height BIGINT NOT NULL,antler_width BIGINT NOT NULL,\
+         snow_magic_power BIGINT NOT NULL,favorite_food VARCHAR(50) NOT NULL,\
+         candies_eaten BIGINT NOT NULL)",
+        "CREATE TABLE contests (id SERIAL PRIMARY KEY,held_at TIMESTAMPTZ NOT NULL DEFAULT NOW())",
+        "CREATE TABLE contest_results (contest_id INT NOT NULL REFERENCES contests ON DELETE CASCADE,\
+         position INT NOT NULL,category VARCHAR(50) NOT NULL,announcement TEXT NOT NULL,value TEXT NOT NULL,\
+         reindeer TEXT[] NOT NULL)",
+    ];
+
+    for query in queries {
+        if let Err(e) = sqlx::query(query).execute(&pool).await {
+            println!("Failed reset reindeer roster: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    StatusCode::OK
+}
+
+async fn list_roster(State(pool): State<PgPool>) -> RosterResult<Json<Vec<DetailReindeer>>> {
+    load(&pool, None).await.map(Json)
+}
+
+async fn get_roster_reindeer(
+    State(pool): State<PgPool>,
+    Path(name): Path<String>,
+) -> RosterResult<Json<DetailReindeer>> {
+    let mut reindeers = load(&pool, Some(&[name])).await?;
+
+    Ok(Json(reindeers.remove(0)))
+}
+
+async fn create_roster_reindeer(
+    State(pool): State<PgPool>,
+    Json(raw): Json<RawReindeer>,
+) -> RosterResult<(StatusCode, Json<DetailReindeer>)> {
+    let reindeer = parse_reindeer(raw)?;
+
+    let created = reindeer.save(&pool, false).await.map_err(database_error)?;
+    if !created {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "The reindeer \"{}\" is already in the roster",
+                reindeer.name
+            ),
+        ));
+    }
+
+    Ok((StatusCode::CREATED, Json(reindeer)))
+}
+
+async fn update_roster_reindeer(
+    State(pool): State<PgPool>,
+    Path(name): Path<String>,
+    Json(raw): Json<RawReindeer>,
+) -> RosterResult<Json<DetailReindeer>> {
+    let reindeer = parse_reindeer(raw)?;
+    if reindeer.name != name {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The reindeer name cannot be changed".into(),
+        ));
+    }
+
+    load(&pool, Some(&[name])).await?;
+    reindeer.save(&pool, true).await.map_err(database_error)?;
+
+    Ok(Json(reindeer))
+}
+
+async fn delete_roster_reindeer(
+    State(pool): State<PgPool>,
+    Path(name): Path<String>,
+) -> RosterResult<StatusCode> {
+    let result = sqlx::query("DELETE FROM reindeer WHERE name = $1")
+        .bind(&name)
+        .execute(&pool)
+        .await
+        .map_err(database_error)?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("The reindeer \"{name}\" is not in the roster"),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn fetch_contests(
+    pool: &PgPool,
+    id: Option<i32>,
+    winner: Option<&str>,
+) -> RosterResult<Vec<ContestRecord>> {
+    let rows = sqlx::query(
+        "SELECT c.id, c.held_at, r.category, r.announcement, r.value, r.reindeer \
+         FROM contests c JOIN contest_results r ON r.contest_id = c.id \
+         WHERE ($1::INT IS NULL OR c.id = $1) \
+         AND ($2::TEXT IS NULL OR EXISTS ( \
+            SELECT 1 FROM contest_results w WHERE w.contest_id = c.id AND $2 = ANY(w.reindeer))) \
+         ORDER BY c.id, r.position",
+    )
+    .bind(id)
+    .bind(winner)
+    .fetch_all(pool)
+    .await
+    .map_err(database_error)?;
+
+    let mut contests: BTreeMap<i32, ContestRecord> = BTreeMap::new();
+    for row in rows {
+        let id: i32 = row.try_get("id").map_err(database_error)?;
+        let result = ContestResultRecord {
+            category: row.try_get("category").map_err(database_error)?,
+            announcement: row.try_get("announcement").map_err(database_error)?,
+            value: row.try_get("value").map_err(database_error)?,
+            reindeer: row.try_get("reindeer").map_err(database_error)?,
+        };
+
+        match contests.get_mut(&id) {
+            Some(contest) => contest.results.push(result),
+            None => {
+                let held_at = row.try_get("held_at").map_err(database_error)?;
+                contests.insert(
+                    id,
+                    ContestRecord {
+                        id,
+                        held_at,
+                        results: vec![result],
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(contests.into_values().collect())
+}
+
+async fn list_contests(
+    State(pool): State<PgPool>,
+    Query(filter): Query<ContestFilter>,
+) -> RosterResult<Json<Vec<ContestRecord>>> {
+    fetch_contests(&pool, None, filter.winner.as_deref())
+        .await
+        .map(Json)
+}
+
+async fn get_contest(
+    State(pool): State<PgPool>,
+    Path(id): Path<i32>,
+) -> RosterResult<Json<ContestRecord>> {
+    fetch_contests(&pool, Some(id), None)
+        .await?
+        .pop()
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("The contest {id} was not found"),
+        ))
+}
+
+pub(super) fn get_roster_routes() -> Router<AppState> {
+    Router::new()
+        .route("/reset", post(reset_roster))
+        .route("/roster", get(list_roster).post(create_roster_reindeer))
+        .route(
+            "/roster/:name",
+            get(get_roster_reindeer)
+                .put(update_roster_reindeer)
+                .delete(delete_roster_reindeer),
+        )
+        .route("/contests", get(list_contests))
+        .route("/contests/:id", get(get_contest))
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeSet, HashMap};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response, Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{
+    gather_reindeers, DetailReindeer, RawReindeer, ReindeerAliases, ReindeerField, RosterSelection,
+};
+
+/// Branches explored before the search settles for the best team found so far.
+const SEARCH_LIMIT: usize = 100_000;
+
+#[derive(Deserialize)]
+pub(super) struct TeamSize {
+    size: usize,
+}
+
+#[derive(Deserialize)]
+pub(super) struct TeamRequest {
+    #[serde(default)]
+    reindeer: Vec<RawReindeer>,
+    roster: Option<RosterSelection>,
+    #[serde(default)]
+    aliases: HashMap<String, ReindeerField>,
+    #[serde(default)]
+    weights: TeamWeights,
+    max_candies: Option<u64>,
+    #[serde(default)]
+    required_foods: BTreeSet<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+struct TeamWeights {
+    strength: f64,
+    speed: f64,
+    snow_magic_power: f64,
+}
+
+#[derive(Serialize, Default)]
+struct ScoreBreakdown {
+    strength: f64,
+    speed: f64,
+    snow_magic_power: f64,
+}
+
+#[derive(Serialize)]
+pub(super) struct TeamResponse {
+    team: Vec<DetailReindeer>,
+    score: f64,
+    breakdown: ScoreBreakdown,
+    candies_eaten: u64,
+    optimal: bool,
+}
+
+/// Branch and bound search over the candidates sorted from the best to the worst score.
+struct TeamSearch {
+    candidates: Vec<(f64, DetailReindeer)>,
+    size: usize,
+    max_candies: Option<u64>,
+    required_foods: BTreeSet<String>,
+    /// For every required food, the last candidate that likes it.
+    last_with_food: HashMap<String, usize>,
+    budget: usize,
+    exhausted: bool,
+    best: Option<(f64, Vec<usize>)>,
+}
+
+impl Default for TeamWeights {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            speed: 1.0,
+            snow_magic_power: 1.0,
+        }
+    }
+}
+
+impl TeamWeights {
+    fn breakdown(&self, reindeer: &DetailReindeer) -> ScoreBreakdown {
+        ScoreBreakdown {
+            strength: self.strength * f64::from(reindeer.strength),
+            speed: self.speed * f64::from(reindeer.speed),
+            snow_magic_power: self.snow_magic_power * f64::from(reindeer.snow_magic_power),
+        }
+    }
+}
+
+impl ScoreBreakdown {
+    fn total(&self) -> f64 {
+        self.strength + self.speed + self.snow_magic_power
+    }
+
+    fn add(mut self, other: ScoreBreakdown) -> Self {
+        self.strength += other.strength;
+        self.speed += other.speed;
+        self.snow_magic_power += other.snow_magic_power;
+        self
+    }
+}
+
+impl TeamSearch {
+    fn new(
+        candidates: Vec<(f64, DetailReindeer)>,
+        size: usize,
+        max_candies: Option<u64>,
+        required_foods: BTreeSet<String>,
+    ) -> Result<Self, String> {
+        let mut search = TeamSearch {
+            candidates,
+            size,
+            max_candies,
+            required_foods,
+            last_with_food: HashMap::new(),
+            budget: SEARCH_LIMIT,
+            exhausted: false,
+            best: None,
+        };
+        search.candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        for (index, (_, reindeer)) in search.candidates.iter().enumerate() {
+            if search.required_foods.contains(&reindeer.favorite_food) {
+                search
+                    .last_with_food
+                    .insert(reindeer.favorite_food.clone(), index);
+            }
+        }
+        if let Some(food) = search
+            .required_foods
+            .iter()
+            .find(|food| !search.last_with_food.contains_key(*food))
+        {
+            return Err(format!("No reindeer likes {food}"));
+        }
+
+        Ok(search)
+    }
+
+    /// Returns the best team found, and whether the search finished so that it is the best one.
+    fn run(mut self) -> (Option<Vec<DetailReindeer>>, bool) {
+        self.explore(0, &mut Vec::with_capacity(self.size), 0.0, 0);
+
+        let team = self.best.map(|(_, chosen)| {
+            chosen
+                .into_iter()
+                .map(|i| self.candidates[i].1.clone())
+                .collect()
+        });
+        (team, !self.exhausted)
+    }
+
+    fn uncovered_foods<'a>(&'a self, chosen: &'a [usize]) -> impl Iterator<Item = &'a String> {
+        self.required_foods.iter().filter(|food| {
+            !chosen
+                .iter()
+                .any(|&i| &&self.candidates[i].1.favorite_food == food)
+        })
+    }
+
+    fn explore(&mut self, start: usize, chosen: &mut Vec<usize>, score: f64, candies: u64) {
+        if self.budget == 0 {
+            self.exhausted = true;
+            return;
+        }
+        self.budget -= 1;
+
+        let remaining = self.size - chosen.len();
+        if remaining == 0 {
+            let improves = !matches!(&self.best, Some((best, _)) if *best >= score);
+            if improves && self.uncovered_foods(chosen).next().is_none() {
+                self.best = Some((score, chosen.clone()));
+            }
+            return;
+        }
+
+        if self.candidates.len() - start < remaining {
+            return;
+        }
+
+        // Every food still missing needs a slot, and a candidate left to fill it.
+        let mut uncovered = 0;
+        for food in self.uncovered_foods(chosen) {
+            if self.last_with_food[food] < start {
+                return;
+            }
+            uncovered += 1;
+        }
+        if uncovered > remaining {
+            return;
+        }
+
+        // The candidates are sorted, so the next ones give the highest score still reachable.
+        let bound = score
+            + self.candidates[start..start + remaining]
+                .iter()
+                .map(|(score, _)| score)
+                .sum::<f64>();
+        if matches!(&self.best, Some((best, _)) if *best >= bound) {
+            return;
+        }
+
+        for index in start..self.candidates.len() {
+            let reindeer_score = self.candidates[index].0;
+            let candies = candies + u64::from(self.candidates[index].1.candies_eaten);
+            if self.max_candies.is_some_and(|max| candies > max) {
+                continue;
+            }
+
+            chosen.push(index);
+            self.explore(index + 1, chosen, score + reindeer_score, candies);
+            chosen.pop();
+        }
+    }
+}
+
+pub(super) async fn build_team(
+    State(pool): State<PgPool>,
+    Query(TeamSize { size }): Query<TeamSize>,
+    Json(request): Json<TeamRequest>,
+) -> response::Result<Json<TeamResponse>, (StatusCode, String)> {
+    let (reindeers, _) = gather_reindeers(
+        &pool,
+        request.reindeer,
+        request.roster,
+        ReindeerAliases::new(request.aliases),
+    )
+    .await?;
+
+    if size == 0 || size > reindeers.len() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "A team of {size} reindeer cannot be picked from {} reindeer",
+                reindeers.len()
+            ),
+        ));
+    }
+
+    let weights = request.weights;
+    let candidates = reindeers
+        .into_iter()
+        .map(|reindeer| (weights.breakdown(&reindeer).total(), reindeer))
+        .collect();
+    let search = TeamSearch::new(
+        candidates,
+        size,
+        request.max_candies,
+        request.required_foods,
+    )
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    // The search can take a while on large rosters, so it stays off the async workers.
+    let (team, optimal) = tokio::task::spawn_blocking(move || search.run())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let team = match (team, optimal) {
+        (Some(team), _) => team,
+        (None, true) => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("No team of {size} reindeer satisfies the constraints"),
+            ))
+        }
+        (None, false) => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("No team of {size} reindeer satisfying the constraints was found in time"),
+            ))
+        }
+    };
+
+    let breakdown = team
+        .iter()
+        .fold(ScoreBreakdown::default(), |acc, reindeer| {
+            acc.add(weights.breakdown(reindeer))
+        });
+    let candies_eaten = team
+        .iter()
+        .map(|reindeer| u64::from(reindeer.candies_eaten))
+        .sum();
+
+    Ok(Json(TeamResponse {
+        score: breakdown.total(),
+        breakdown,
+        candies_eaten,
+        team,
+        optimal,
+    }))
+}
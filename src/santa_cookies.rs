@@ -1,123 +1,588 @@
 use axum::{
-    http::{header::COOKIE, HeaderMap, HeaderValue},
-    routing::get,
+    body::Bytes,
+    extract::{Query, State},
+    http::{
+        header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+        HeaderMap, StatusCode,
+    },
+    response::{self, AppendHeaders, IntoResponse},
+    routing::{get, post},
     Json, Router,
 };
-use base64::prelude::*;
+use base64::{
+    alphabet,
+    engine::{general_purpose::GeneralPurpose, DecodePaddingMode, GeneralPurposeConfig},
+    prelude::*,
+};
 use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
 
 use crate::AppState;
 
+mod pantry;
+mod planner;
+mod sealing;
+
+use planner::{CookieRecipe, PlanGoal, PlanSummary};
+
+pub use sealing::RecipeSealer;
+
+/// Either a single `recipe` or several named `recipes` to plan a mix of, never both.
 #[derive(Debug, Deserialize)]
 struct Order {
-    recipe: HashMap<String, u64>,
-    pantry: HashMap<String, u64>,
+    recipe: Option<HashMap<String, Quantity>>,
+    recipes: Option<HashMap<String, CookieRecipe>>,
+    pantry: HashMap<String, Quantity>,
+    #[serde(default)]
+    maximize: PlanGoal,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    G,
+    Kg,
+    Ml,
+    L,
+    Cups,
+    Pieces,
+}
+
+/// Amount of an ingredient, either a bare number as in the original orders or with a unit.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(untagged)]
+enum Quantity {
+    Plain(u64),
+    Measured { quantity: u64, unit: Unit },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum Base64Alphabet {
+    #[default]
+    Standard,
+    UrlSafe,
+}
+
+/// Base64 variant of plain recipe cookies, which sealed cookies always encode their own way.
+#[derive(Debug, Deserialize)]
+struct EncodeOptions {
+    alphabet: Option<Base64Alphabet>,
+    padding: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct EncodedRecipe {
+    recipe: String,
+}
+
+#[derive(Debug)]
+enum RecipeError {
+    NoCookies,
+    MissingRecipe,
+    InvalidRecipe(String),
+    InvalidOrder,
+    InvalidQuantity(String),
+    UnsupportedContentType,
+    UnsupportedEncoding,
+    UnknownTransaction(String),
+    PantryUnavailable,
+}
+
+/// Form-encoded `POST /7/bake` body, with the order as JSON in the field named like the cookie.
+#[derive(Debug, Deserialize)]
+struct BakeForm {
+    recipe: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RecipeErrorDto {
+    error: &'static str,
+    message: String,
 }
 
 #[derive(Debug, Serialize)]
 struct OrderResponse {
     cookies: u64,
-    pantry: HashMap<String, u64>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    plan: Option<PlanSummary>,
+    pantry: HashMap<String, Quantity>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShoppingListQuery {
+    cookies: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ShoppingList {
+    cookies: u64,
+    bottleneck: Option<String>,
+    missing: HashMap<String, Quantity>,
+}
+
+impl Unit {
+    /// Smallest unit of the same dimension and how many of it make one of this unit.
+    fn base(&self) -> (Unit, u64) {
+        match self {
+            Unit::G => (Unit::G, 1),
+            Unit::Kg => (Unit::G, 1000),
+            Unit::Ml => (Unit::Ml, 1),
+            Unit::L => (Unit::Ml, 1000),
+            Unit::Cups => (Unit::Ml, 240),
+            Unit::Pieces => (Unit::Pieces, 1),
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            Unit::G => "g",
+            Unit::Kg => "kg",
+            Unit::Ml => "ml",
+            Unit::L => "l",
+            Unit::Cups => "cups",
+            Unit::Pieces => "pieces",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Unit> {
+        [
+            Unit::G,
+            Unit::Kg,
+            Unit::Ml,
+            Unit::L,
+            Unit::Cups,
+            Unit::Pieces,
+        ]
+        .into_iter()
+        .find(|unit| unit.key() == key)
+    }
+}
+
+impl Quantity {
+    fn amount(&self) -> u64 {
+        match self {
+            Quantity::Plain(quantity) | Quantity::Measured { quantity, .. } => *quantity,
+        }
+    }
+
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Quantity::Plain(_) => None,
+            Quantity::Measured { unit, .. } => Some(*unit),
+        }
+    }
+
+    /// Amount in the smallest unit of its dimension, reading a bare number in `fallback`.
+    fn base_amount(&self, ingredient: &str, fallback: Option<Unit>) -> Result<u64, RecipeError> {
+        match self.unit().or(fallback) {
+            Some(unit) => checked_mul(ingredient, self.amount(), unit.base().1),
+            None => Ok(self.amount()),
+        }
+    }
+
+    /// Converts the recipe and pantry quantities of an ingredient to the same unit. A bare
+    /// number is taken to be in the unit of the other side.
+    fn common_amounts(
+        ingredient: &str,
+        recipe: Quantity,
+        pantry: Quantity,
+    ) -> Result<(u64, u64), RecipeError> {
+        if let (Some(recipe_unit), Some(pantry_unit)) = (recipe.unit(), pantry.unit()) {
+            if recipe_unit.base().0 != pantry_unit.base().0 {
+                return Err(RecipeError::InvalidQuantity(format!(
+                    "The units given for {ingredient} cannot be converted into each other"
+                )));
+            }
+        }
+
+        Ok((
+            recipe.base_amount(ingredient, pantry.unit())?,
+            pantry.base_amount(ingredient, recipe.unit())?,
+        ))
+    }
+
+    /// Expresses an amount of the unit shared with `other` back in the unit of this entry,
+    /// falling back to the smallest unit when it is not a whole number of it.
+    fn with_amount(&self, amount: u64, other: Quantity) -> Quantity {
+        let Some(unit) = self.unit().or(other.unit()) else {
+            return Quantity::Plain(amount);
+        };
+
+        let (base, factor) = unit.base();
+        match amount % factor {
+            0 => Quantity::Measured {
+                quantity: amount / factor,
+                unit,
+            },
+            _ => Quantity::Measured {
+                quantity: amount,
+                unit: base,
+            },
+        }
+    }
+}
+
+fn checked_mul(ingredient: &str, left: u64, right: u64) -> Result<u64, RecipeError> {
+    left.checked_mul(right).ok_or_else(|| {
+        RecipeError::InvalidQuantity(format!("The quantity of {ingredient} is too large"))
+    })
 }
 
 impl Order {
-    fn bake(self) -> OrderResponse {
-        let cookies_total = self
-            .recipe
-            .iter()
-            .filter(|(_, &recipe_quantity)| recipe_quantity != 0)
-            .flat_map(|(ingredient, recipe_quantity)| {
-                self.pantry
-                    .get(ingredient)
-                    .map(|pantry_quantity| pantry_quantity / recipe_quantity)
-            })
-            .min()
-            .unwrap_or(0);
-
-        let remain = self.pantry.into_iter().fold(
-            HashMap::new(),
-            move |mut acc, (ingredient, pantry_quantity)| {
-                let recipe_quantity = self.recipe.get(&ingredient);
-
-                if let Some(recipe_quantity) = recipe_quantity {
-                    acc.insert(
-                        ingredient,
-                        pantry_quantity - cookies_total * recipe_quantity,
-                    );
-                } else {
-                    acc.insert(ingredient, pantry_quantity);
+    fn bake(self) -> Result<OrderResponse, RecipeError> {
+        match (&self.recipe, &self.recipes) {
+            (Some(recipe), None) => bake_recipe(recipe, &self.pantry),
+            (None, Some(recipes)) => planner::plan(recipes, &self.pantry, self.maximize),
+            _ => Err(RecipeError::InvalidOrder),
+        }
+    }
+
+    /// Lists what has to be bought to bake `cookies` cookies, along with the ingredient that
+    /// currently runs out first. Ingredients absent from the pantry count as empty.
+    fn shopping_list(self, cookies: u64) -> Result<ShoppingList, RecipeError> {
+        let (Some(recipe), None) = (&self.recipe, &self.recipes) else {
+            return Err(RecipeError::InvalidOrder);
+        };
+
+        let mut bottleneck: Option<(u64, &str)> = None;
+        let mut missing = HashMap::new();
+        for (ingredient, recipe_quantity) in recipe {
+            if recipe_quantity.amount() == 0 {
+                continue;
+            }
+
+            let pantry_quantity = self
+                .pantry
+                .get(ingredient)
+                .copied()
+                .unwrap_or(Quantity::Plain(0));
+            let (recipe_amount, pantry_amount) =
+                Quantity::common_amounts(ingredient, *recipe_quantity, pantry_quantity)?;
+
+            let supported = (pantry_amount / recipe_amount, ingredient.as_str());
+            if !matches!(bottleneck, Some(current) if current <= supported) {
+                bottleneck = Some(supported);
+            }
+
+            let needed = checked_mul(ingredient, cookies, recipe_amount)?;
+            if needed > pantry_amount {
+                let quantity = recipe_quantity.with_amount(needed - pantry_amount, pantry_quantity);
+                missing.insert(ingredient.clone(), quantity);
+            }
+        }
+
+        Ok(ShoppingList {
+            cookies,
+            bottleneck: bottleneck.map(|(_, ingredient)| ingredient.to_string()),
+            missing,
+        })
+    }
+}
+
+fn bake_recipe(
+    recipe: &HashMap<String, Quantity>,
+    pantry: &HashMap<String, Quantity>,
+) -> Result<OrderResponse, RecipeError> {
+    let mut amounts = HashMap::new();
+    for (ingredient, recipe_quantity) in recipe {
+        if recipe_quantity.amount() == 0 {
+            continue;
+        }
+
+        if let Some(pantry_quantity) = pantry.get(ingredient) {
+            let common = Quantity::common_amounts(ingredient, *recipe_quantity, *pantry_quantity)?;
+            amounts.insert(ingredient.as_str(), common);
+        }
+    }
+
+    let cookies_total = amounts
+        .values()
+        .map(|(recipe_amount, pantry_amount)| pantry_amount / recipe_amount)
+        .min()
+        .unwrap_or(0);
+
+    let mut remain = HashMap::new();
+    for (ingredient, pantry_quantity) in pantry {
+        let quantity = match amounts.get(ingredient.as_str()) {
+            Some((recipe_amount, pantry_amount)) => {
+                let used = checked_mul(ingredient, cookies_total, *recipe_amount)?;
+                let left = pantry_amount.checked_sub(used).ok_or_else(|| {
+                    RecipeError::InvalidQuantity(format!("Not enough {ingredient} in the pantry"))
+                })?;
+
+                pantry_quantity.with_amount(left, recipe[ingredient])
+            }
+            None => *pantry_quantity,
+        };
+
+        remain.insert(ingredient.clone(), quantity);
+    }
+
+    Ok(OrderResponse {
+        cookies: cookies_total,
+        plan: None,
+        pantry: remain,
+    })
+}
+
+/// Decoders for every variant `/7/encode` produces, padded or not.
+const LENIENT_ENGINES: [GeneralPurpose; 2] = [
+    GeneralPurpose::new(
+        &alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    ),
+    GeneralPurpose::new(
+        &alphabet::URL_SAFE,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    ),
+];
+
+fn decode_recipe(encoded: &[u8]) -> Option<Vec<u8>> {
+    LENIENT_ENGINES
+        .iter()
+        .find_map(|engine| engine.decode(encoded).ok())
+}
+
+fn encode_recipe(raw_recipe: &[u8], options: &EncodeOptions) -> String {
+    match (
+        options.alphabet.unwrap_or_default(),
+        options.padding.unwrap_or(true),
+    ) {
+        (Base64Alphabet::Standard, true) => BASE64_STANDARD.encode(raw_recipe),
+        (Base64Alphabet::Standard, false) => BASE64_STANDARD_NO_PAD.encode(raw_recipe),
+        (Base64Alphabet::UrlSafe, true) => BASE64_URL_SAFE.encode(raw_recipe),
+        (Base64Alphabet::UrlSafe, false) => BASE64_URL_SAFE_NO_PAD.encode(raw_recipe),
+    }
+}
+
+/// Cookies in the order the client sent them, so a duplicated name keeps every value.
+struct CookieJar(Vec<(String, Vec<u8>)>);
+
+impl CookieJar {
+    /// Parses every `Cookie` header following the cookie-string grammar of RFC 6265, ignoring
+    /// pairs without a value or a valid name as user agents do (section 5.2).
+    fn parse(headers: &HeaderMap) -> Result<Self, RecipeError> {
+        let mut cookies = Vec::new();
+
+        for header in headers.get_all(COOKIE) {
+            for pair in header.as_bytes().split(|&byte| byte == b';') {
+                let pair = trim_whitespace(pair);
+                if pair.is_empty() {
+                    continue;
                 }
 
-                acc
-            },
-        );
+                let Some(equal) = pair.iter().position(|&byte| byte == b'=') else {
+                    continue;
+                };
+                let (name, value) = (trim_whitespace(&pair[..equal]), &pair[equal + 1..]);
+
+                let name = std::str::from_utf8(name)
+                    .ok()
+                    .filter(|name| !name.is_empty() && name.bytes().all(is_token_byte));
+                let Some(name) = name else {
+                    continue;
+                };
+
+                let mut value = trim_whitespace(value);
+                if value.len() >= 2 && value.starts_with(b"\"") && value.ends_with(b"\"") {
+                    value = &value[1..value.len() - 1];
+                }
 
-        OrderResponse {
-            cookies: cookies_total,
-            pantry: remain,
+                cookies.push((name.to_string(), value.to_vec()));
+            }
         }
+
+        if cookies.is_empty() {
+            return Err(RecipeError::NoCookies);
+        }
+
+        Ok(Self(cookies))
     }
+
+    /// Value of the first cookie with the name, the most specific one for user agents.
+    fn first(&self, name: &str) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(cookie, _)| cookie == name)
+            .map(|(_, value)| value.as_slice())
+    }
+}
+
+fn trim_whitespace(bytes: &[u8]) -> &[u8] {
+    let is_whitespace = |byte: &u8| *byte == b' ' || *byte == b'\t';
+    let start = bytes
+        .iter()
+        .position(|byte| !is_whitespace(byte))
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|byte| !is_whitespace(byte))
+        .map_or(start, |end| end + 1);
+
+    &bytes[start..end]
 }
 
-fn get_cookies_map(
-    headers: &HeaderMap,
-) -> axum::response::Result<HashMap<String, HeaderValue>, String> {
-    let cookies = headers.get_all(COOKIE);
-    if cookies.iter().count() == 0 {
-        return Err("Cookies are empty".into());
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+impl RecipeError {
+    fn code(&self) -> &'static str {
+        match self {
+            RecipeError::NoCookies => "no_cookies",
+            RecipeError::MissingRecipe => "missing_recipe",
+            RecipeError::InvalidRecipe(_) => "invalid_recipe",
+            RecipeError::InvalidOrder => "invalid_order",
+            RecipeError::InvalidQuantity(_) => "invalid_quantity",
+            RecipeError::UnsupportedContentType => "unsupported_content_type",
+            RecipeError::UnsupportedEncoding => "unsupported_encoding",
+            RecipeError::UnknownTransaction(_) => "unknown_transaction",
+            RecipeError::PantryUnavailable => "pantry_unavailable",
+        }
     }
 
-    let mut map = HashMap::new();
+    fn status(&self) -> StatusCode {
+        match self {
+            RecipeError::UnsupportedContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            RecipeError::UnknownTransaction(_) => StatusCode::NOT_FOUND,
+            RecipeError::PantryUnavailable => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
 
-    cookies
-        .into_iter()
-        .flat_map(|value| value.as_bytes().split(|v| v == &b';').collect::<Vec<_>>())
-        .map(|cookie| cookie.splitn(2, |&v| v == b'=').collect::<Vec<_>>())
-        .for_each(|cookie| {
-            map.insert(
-                String::from_utf8(cookie[0].into()).unwrap(),
-                HeaderValue::from_bytes(cookie[1]).unwrap(),
-            );
-        });
+    fn message(self) -> String {
+        match self {
+            RecipeError::NoCookies => "Cookies are empty".into(),
+            RecipeError::MissingRecipe => "Missing cookie".into(),
+            RecipeError::InvalidOrder => "Order does not have the correct shape".into(),
+            RecipeError::UnsupportedContentType => "Orders are sent as JSON or form-encoded".into(),
+            RecipeError::UnsupportedEncoding => {
+                "Sealed recipe cookies do not take an alphabet or padding".into()
+            }
+            RecipeError::PantryUnavailable => "The pantry could not be reached".into(),
+            RecipeError::InvalidRecipe(message)
+            | RecipeError::InvalidQuantity(message)
+            | RecipeError::UnknownTransaction(message) => message,
+        }
+    }
+}
+
+impl IntoResponse for RecipeError {
+    fn into_response(self) -> response::Response {
+        let status = self.status();
+        let error = RecipeErrorDto {
+            error: self.code(),
+            message: self.message(),
+        };
 
-    Ok(map)
+        (status, Json(error)).into_response()
+    }
 }
 
-async fn get_encoded_cookies_recipe(headers: HeaderMap) -> axum::response::Result<String, String> {
-    let cookies = get_cookies_map(&headers)?;
-    let encode_recipe = cookies.get("recipe").ok_or("Missing cookie")?;
+/// Reads the recipe cookie, opening it first when it was signed or encrypted.
+fn read_recipe_cookie(headers: &HeaderMap, sealer: &RecipeSealer) -> Result<Vec<u8>, RecipeError> {
+    let cookies = CookieJar::parse(headers)?;
+    let encode_recipe = cookies.first("recipe").ok_or(RecipeError::MissingRecipe)?;
+
+    match sealer
+        .open(encode_recipe)
+        .map_err(RecipeError::InvalidRecipe)?
+    {
+        Some(raw_recipe) => Ok(raw_recipe),
+        None => decode_recipe(encode_recipe)
+            .ok_or_else(|| RecipeError::InvalidRecipe("The recipe cookie is not valid".into())),
+    }
+}
 
-    let raw_recipe = BASE64_STANDARD
-        .decode(encode_recipe)
-        .map_err(|_| "The recipe cookie is not valid")?;
+async fn get_encoded_cookies_recipe(
+    State(sealer): State<RecipeSealer>,
+    headers: HeaderMap,
+) -> response::Result<String, RecipeError> {
+    let raw_recipe = read_recipe_cookie(&headers, &sealer)?;
 
-    let decode_recipe = String::from_utf8(raw_recipe).unwrap();
+    let decode_recipe = String::from_utf8(raw_recipe)
+        .map_err(|_| RecipeError::InvalidRecipe("The recipe cookie is not valid UTF-8".into()))?;
 
     Ok(decode_recipe)
 }
 
 async fn get_baked_cookies(
+    State(sealer): State<RecipeSealer>,
     headers: HeaderMap,
-) -> axum::response::Result<Json<OrderResponse>, String> {
-    let cookies = get_cookies_map(&headers)?;
-    let encode_recipe = cookies.get("recipe").ok_or("Missing cookie")?;
+) -> response::Result<Json<OrderResponse>, RecipeError> {
+    let raw_recipe = read_recipe_cookie(&headers, &sealer)?;
 
-    let raw_recipe = BASE64_STANDARD
-        .decode(encode_recipe)
-        .map_err(|_| "Invalid value for recipe cookie")?;
+    Ok(Json(bake_order(&raw_recipe)?))
+}
 
-    let recipe = serde_json::from_slice::<Order>(&raw_recipe)
-        .map_err(|_| "Order does not have the correct shape")?;
+async fn post_baked_cookies(
+    headers: HeaderMap,
+    body: Bytes,
+) -> response::Result<Json<OrderResponse>, RecipeError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
 
-    let remain = recipe.bake();
+    let remain = match content_type.as_deref() {
+        Some("application/json") => bake_order(&body)?,
+        Some("application/x-www-form-urlencoded") => {
+            let form = serde_urlencoded::from_bytes::<BakeForm>(&body)
+                .map_err(|_| RecipeError::InvalidOrder)?;
+            bake_order(form.recipe.as_bytes())?
+        }
+        _ => return Err(RecipeError::UnsupportedContentType),
+    };
 
     Ok(Json(remain))
 }
 
+fn bake_order(raw_recipe: &[u8]) -> Result<OrderResponse, RecipeError> {
+    let recipe =
+        serde_json::from_slice::<Order>(raw_recipe).map_err(|_| RecipeError::InvalidOrder)?;
+
+    recipe.bake()
+}
+
+async fn get_shopping_list(
+    Query(query): Query<ShoppingListQuery>,
+    body: Bytes,
+) -> response::Result<Json<ShoppingList>, RecipeError> {
+    let order = serde_json::from_slice::<Order>(&body).map_err(|_| RecipeError::InvalidOrder)?;
+
+    Ok(Json(order.shopping_list(query.cookies)?))
+}
+
+async fn encode_cookies_recipe(
+    State(sealer): State<RecipeSealer>,
+    Query(options): Query<EncodeOptions>,
+    body: Bytes,
+) -> response::Result<impl IntoResponse, RecipeError> {
+    serde_json::from_slice::<Order>(&body).map_err(|_| RecipeError::InvalidOrder)?;
+
+    let recipe = match sealer.seal(&body) {
+        Some(_) if options.alphabet.is_some() || options.padding.is_some() => {
+            return Err(RecipeError::UnsupportedEncoding)
+        }
+        Some(sealed) => sealed,
+        None => encode_recipe(&body, &options),
+    };
+    let cookie = format!("recipe={recipe}; Path=/");
+
+    Ok((
+        AppendHeaders([(SET_COOKIE, cookie)]),
+        Json(EncodedRecipe { recipe }),
+    ))
+}
+
 pub fn get_cookies_recipe_routes() -> Router<AppState> {
     Router::new()
         .route("/decode", get(get_encoded_cookies_recipe))
-        .route("/bake", get(get_baked_cookies))
+        .route("/bake", get(get_baked_cookies).post(post_baked_cookies))
+        .route("/encode", post(encode_cookies_recipe))
+        .route("/shopping-list", post(get_shopping_list))
+        .merge(pantry::get_pantry_routes())
 }
@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    response,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool, Postgres, Row, Transaction};
+
+use super::{
+    planner::{CookieRecipe, PlanGoal},
+    Order, OrderResponse, Quantity, RecipeError, Unit,
+};
+use crate::AppState;
+
+type Stock = HashMap<String, Quantity>;
+
+/// An order baked against the stored pantry instead of one sent along.
+#[derive(Debug, Deserialize)]
+struct PantryOrder {
+    recipe: Option<HashMap<String, Quantity>>,
+    recipes: Option<HashMap<String, CookieRecipe>>,
+    #[serde(default)]
+    maximize: PlanGoal,
+}
+
+#[derive(Debug, Serialize)]
+struct PantryBake {
+    transaction_id: i32,
+    #[serde(flatten)]
+    order: OrderResponse,
+}
+
+#[derive(Debug, Serialize)]
+struct PantryTransaction {
+    id: i32,
+    kind: String,
+    made_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cookies: Option<i64>,
+    changes: BTreeMap<String, PantryChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct PantryChange {
+    change: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<Unit>,
+}
+
+fn database_error(error: sqlx::Error) -> RecipeError {
+    println!("{error:#?}");
+    RecipeError::PantryUnavailable
+}
+
+fn stored_amount(ingredient: &str, amount: u64) -> Result<i64, RecipeError> {
+    i64::try_from(amount).map_err(|_| {
+        RecipeError::InvalidQuantity(format!("The quantity of {ingredient} is too large"))
+    })
+}
+
+/// Reads the stored pantry.
+async fn read_stock<'e>(executor: impl PgExecutor<'e>) -> Result<Stock, RecipeError> {
+    let rows = sqlx::query("SELECT ingredient, quantity, unit FROM pantry")
+        .fetch_all(executor)
+        .await
+        .map_err(database_error)?;
+
+    rows.into_iter()
+        .map(|row| {
+            let ingredient: String = row.try_get("ingredient")?;
+            let quantity = row.try_get::<i64, _>("quantity")? as u64;
+            let unit = row
+                .try_get::<Option<String>, _>("unit")?
+                .and_then(|unit| Unit::from_key(&unit));
+
+            let quantity = match unit {
+                Some(unit) => Quantity::Measured { quantity, unit },
+                None => Quantity::Plain(quantity),
+            };
+
+            Ok((ingredient, quantity))
+        })
+        .collect::<Result<_, sqlx::Error>>()
+        .map_err(database_error)
+}
+
+/// Reads the stored pantry, keeping every other change out until the transaction ends.
+/// Row locks would not cover ingredients that are not stocked yet, so the whole table is
+/// locked; plain reads still go through.
+async fn lock_stock(transaction: &mut Transaction<'_, Postgres>) -> Result<Stock, RecipeError> {
+    sqlx::query("LOCK TABLE pantry IN EXCLUSIVE MODE")
+        .execute(&mut **transaction)
+        .await
+        .map_err(database_error)?;
+
+    read_stock(&mut **transaction).await
+}
+
+/// Stores the new stock of every changed ingredient, in the smallest unit of its dimension,
+/// and records how much each one moved.
+async fn record_transaction(
+    transaction: &mut Transaction<'_, Postgres>,
+    kind: &str,
+    cookies: Option<u64>,
+    before: &Stock,
+    after: &Stock,
+) -> Result<i32, RecipeError> {
+    let cookies = cookies
+        .map(|cookies| stored_amount("cookies", cookies))
+        .transpose()?;
+    let transaction_id: i32 =
+        sqlx::query("INSERT INTO pantry_transactions(kind, cookies) VALUES($1, $2) RETURNING id")
+            .bind(kind)
+            .bind(cookies)
+            .fetch_one(&mut **transaction)
+            .await
+            .and_then(|row| row.try_get("id"))
+            .map_err(database_error)?;
+
+    for (ingredient, quantity) in after {
+        let unit = quantity.unit().map(|unit| unit.base().0);
+        let amount = stored_amount(ingredient, quantity.base_amount(ingredient, None)?)?;
+        let previous = match before.get(ingredient) {
+            Some(previous) => stored_amount(ingredient, previous.base_amount(ingredient, unit)?)?,
+            None => 0,
+        };
+        if previous == amount && before.contains_key(ingredient) {
+            continue;
+        }
+
+        sqlx::query(
+            "INSERT INTO pantry(ingredient, quantity, unit) VALUES($1, $2, $3) \
+             ON CONFLICT (ingredient) DO UPDATE SET quantity = $2, unit = $3",
+        )
+        .bind(ingredient)
+        .bind(amount)
+        .bind(unit.map(|unit| unit.key()))
+        .execute(&mut **transaction)
+        .await
+        .map_err(database_error)?;
+
+        sqlx::query(
+            "INSERT INTO pantry_changes(transaction_id, ingredient, change, unit) \
+             VALUES($1, $2, $3, $4)",
+        )
+        .bind(transaction_id)
+        .bind(ingredient)
+        .bind(amount - previous)
+        .bind(unit.map(|unit| unit.key()))
+        .execute(&mut **transaction)
+        .await
+        .map_err(database_error)?;
+    }
+
+    Ok(transaction_id)
+}
+
+async fn reset_pantry(State(pool): State<PgPool>) -> StatusCode {
+    let queries = [
+        "DROP TABLE IF EXISTS pantry_changes",
+        "DROP TABLE IF EXISTS pantry_transactions",
+        "DROP TABLE IF EXISTS pantry",
+        "CREATE TABLE pantry (ingredient VARCHAR(50) PRIMARY KEY,\
+         quantity BIGINT NOT NULL CHECK (quantity >= 0),unit VARCHAR(10))",
+        "CREATE TABLE pantry_transactions (id SERIAL PRIMARY KEY,kind VARCHAR(10) NOT NULL,\
+         cookies BIGINT,made_at TIMESTAMPTZ NOT NULL DEFAULT NOW())",
+        "CREATE TABLE pantry_changes (transaction_id INT NOT NULL REFERENCES pantry_transactions \
+         ON DELETE CASCADE,ingredient VARCHAR(50) NOT NULL,change BIGINT NOT NULL,unit VARCHAR(10))",
+    ];
+
+    for query in queries {
+        if let Err(e) = sqlx::query(query).execute(&pool).await {
+            println!("Failed reset pantry: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    StatusCode::OK
+}
+
+async fn get_pantry(State(pool): State<PgPool>) -> response::Result<Json<Stock>, RecipeError> {
+    read_stock(&pool).await.map(Json)
+}
+
+async fn restock_pantry(
+    State(pool): State<PgPool>,
+    body: Bytes,
+) -> response::Result<Json<Stock>, RecipeError> {
+    let delivery = serde_json::from_slice::<Stock>(&body).map_err(|_| RecipeError::InvalidOrder)?;
+
+    let mut transaction = pool.begin().await.map_err(database_error)?;
+    let before = lock_stock(&mut transaction).await?;
+
+    let mut after = before.clone();
+    for (ingredient, quantity) in delivery {
+        let stocked = before
+            .get(&ingredient)
+            .copied()
+            .unwrap_or(Quantity::Plain(0));
+        let (delivered, stocked_amount) = Quantity::common_amounts(&ingredient, quantity, stocked)?;
+        let total = delivered.checked_add(stocked_amount).ok_or_else(|| {
+            RecipeError::InvalidQuantity(format!("The quantity of {ingredient} is too large"))
+        })?;
+
+        let unit = quantity.unit().or(stocked.unit()).map(|unit| unit.base().0);
+        let total = match unit {
+            Some(unit) => Quantity::Measured {
+                quantity: total,
+                unit,
+            },
+            None => Quantity::Plain(total),
+        };
+        after.insert(ingredient, total);
+    }
+
+    record_transaction(&mut transaction, "restock", None, &before, &after).await?;
+    transaction.commit().await.map_err(database_error)?;
+
+    Ok(Json(after))
+}
+
+async fn bake_from_pantry(
+    State(pool): State<PgPool>,
+    body: Bytes,
+) -> response::Result<Json<PantryBake>, RecipeError> {
+    let order =
+        serde_json::from_slice::<PantryOrder>(&body).map_err(|_| RecipeError::InvalidOrder)?;
+
+    let mut transaction = pool.begin().await.map_err(database_error)?;
+    let before = lock_stock(&mut transaction).await?;
+
+    let order = Order {
+        recipe: order.recipe,
+        recipes: order.recipes,
+        pantry: before.clone(),
+        maximize: order.maximize,
+    }
+    .bake()?;
+
+    let transaction_id = record_transaction(
+        &mut transaction,
+        "bake",
+        Some(order.cookies),
+        &before,
+        &order.pantry,
+    )
+    .await?;
+    transaction.commit().await.map_err(database_error)?;
+
+    Ok(Json(PantryBake {
+        transaction_id,
+        order,
+    }))
+}
+
+async fn fetch_transactions(
+    pool: &PgPool,
+    id: Option<i32>,
+) -> Result<Vec<PantryTransaction>, RecipeError> {
+    let rows = sqlx::query(
+        "SELECT t.id, t.kind, t.cookies, t.made_at, c.ingredient, c.change, c.unit \
+         FROM pantry_transactions t LEFT JOIN pantry_changes c ON c.transaction_id = t.id \
+         WHERE ($1::INT IS NULL OR t.id = $1) ORDER BY t.id",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await
+    .map_err(database_error)?;
+
+    let mut transactions: BTreeMap<i32, PantryTransaction> = BTreeMap::new();
+    for row in rows {
+        let read = || -> Result<_, sqlx::Error> {
+            let id: i32 = row.try_get("id")?;
+            let change = row
+                .try_get::<Option<String>, _>("ingredient")?
+                .map(|ingredient| -> Result<_, sqlx::Error> {
+                    let change = PantryChange {
+                        change: row.try_get("change")?,
+                        unit: row
+                            .try_get::<Option<String>, _>("unit")?
+                            .and_then(|unit| Unit::from_key(&unit)),
+                    };
+                    Ok((ingredient, change))
+                })
+                .transpose()?;
+
+            let transaction = PantryTransaction {
+                id,
+                kind: row.try_get("kind")?,
+                made_at: row.try_get("made_at")?,
+                cookies: row.try_get("cookies")?,
+                changes: BTreeMap::new(),
+            };
+            Ok((transaction, change))
+        };
+
+        let (transaction, change) = read().map_err(database_error)?;
+        let entry = transactions.entry(transaction.id).or_insert(transaction);
+        if let Some((ingredient, change)) = change {
+            entry.changes.insert(ingredient, change);
+        }
+    }
+
+    Ok(transactions.into_values().collect())
+}
+
+async fn list_transactions(
+    State(pool): State<PgPool>,
+) -> response::Result<Json<Vec<PantryTransaction>>, RecipeError> {
+    fetch_transactions(&pool, None).await.map(Json)
+}
+
+async fn get_transaction(
+    State(pool): State<PgPool>,
+    Path(id): Path<i32>,
+) -> response::Result<Json<PantryTransaction>, RecipeError> {
+    fetch_transactions(&pool, Some(id))
+        .await?
+        .pop()
+        .map(Json)
+        .ok_or_else(|| {
+            RecipeError::UnknownTransaction(format!("The pantry has no transaction {id}"))
+        })
+}
+
+pub(super) fn get_pantry_routes() -> Router<AppState> {
+    Router::new()
+        .route("/pantry", get(get_pantry))
+        .route("/pantry/reset", post(reset_pantry))
+        .route("/pantry/restock", post(restock_pantry))
+        .route("/pantry/bake", post(bake_from_pantry))
+        .route("/pantry/transactions", get(list_transactions))
+        .route("/pantry/transactions/:id", get(get_transaction))
+}
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+use super::{checked_mul, OrderResponse, Quantity, RecipeError};
+
+/// Branches explored before the planner settles for the best mix found so far.
+const SEARCH_LIMIT: usize = 100_000;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CookieRecipe {
+    recipe: HashMap<String, Quantity>,
+    #[serde(default = "default_value")]
+    value: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum PlanGoal {
+    #[default]
+    Value,
+    Count,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct PlanSummary {
+    plan: HashMap<String, u64>,
+    value: u64,
+    optimal: bool,
+}
+
+struct Candidate<'a> {
+    name: &'a str,
+    value: u64,
+    usage: Vec<u64>,
+}
+
+struct BakePlanner<'a> {
+    candidates: Vec<Candidate<'a>>,
+    budget: usize,
+    exhausted: bool,
+    best: Option<(u128, Vec<u64>)>,
+}
+
+fn default_value() -> u64 {
+    1
+}
+
+impl<'a> BakePlanner<'a> {
+    fn run(mut self, stock: &[u64]) -> (Vec<(&'a str, u64)>, bool) {
+        self.candidates
+            .sort_by(|a, b| b.value.cmp(&a.value).then(a.name.cmp(b.name)));
+        self.explore(0, stock, 0, &mut vec![0; self.candidates.len()]);
+
+        let counts = self
+            .best
+            .map(|(_, counts)| counts)
+            .unwrap_or_else(|| vec![0; self.candidates.len()]);
+        let plan = self
+            .candidates
+            .iter()
+            .zip(counts)
+            .map(|(candidate, count)| (candidate.name, count))
+            .collect();
+
+        (plan, !self.exhausted)
+    }
+
+    fn max_count(&self, index: usize, stock: &[u64]) -> u64 {
+        self.candidates[index]
+            .usage
+            .iter()
+            .zip(stock)
+            .filter(|(usage, _)| **usage != 0)
+            .map(|(usage, stock)| stock / usage)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Highest value the candidates from `start` on could still add, relaxing whole cookies to
+    /// fractions: each recipe alone, and each ingredient shared by all of them.
+    fn bound(&self, start: usize, stock: &[u64]) -> f64 {
+        let rest = &self.candidates[start..];
+        let per_recipe = (start..self.candidates.len())
+            .map(|index| self.candidates[index].value as f64 * self.max_count(index, stock) as f64)
+            .sum::<f64>();
+
+        stock
+            .iter()
+            .enumerate()
+            .filter_map(|(ingredient, &stock)| {
+                let best_ratio = rest
+                    .iter()
+                    .map(|candidate| match candidate.usage[ingredient] {
+                        0 => None,
+                        usage => Some(candidate.value as f64 / usage as f64),
+                    })
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .fold(0.0, f64::max);
+
+                Some(stock as f64 * best_ratio)
+            })
+            .fold(per_recipe, f64::min)
+    }
+
+    /// Values are whole numbers, so a branch has to reach one more than the best to improve it.
+    /// The slack keeps float rounding from cutting the optimum.
+    fn improvable(&self, reachable: f64) -> bool {
+        !matches!(&self.best, Some((best, _)) if reachable * (1.0 + 1e-9) < (*best + 1) as f64)
+    }
+
+    fn explore(&mut self, index: usize, stock: &[u64], value: u128, counts: &mut Vec<u64>) {
+        if index == self.candidates.len() {
+            if !matches!(&self.best, Some((best, _)) if *best >= value) {
+                self.best = Some((value, counts.clone()));
+            }
+            return;
+        }
+
+        if !self.improvable(value as f64 + self.bound(index, stock)) {
+            return;
+        }
+
+        // The last recipe simply takes whatever is left.
+        let most = self.max_count(index, stock);
+        let fewest = if index + 1 == self.candidates.len() {
+            most
+        } else {
+            0
+        };
+        // Leaving this recipe's share of the pantry to the others bounds every smaller count.
+        let others = self.bound(index + 1, stock);
+
+        for count in (fewest..=most).rev() {
+            let candidate_value = count as f64 * self.candidates[index].value as f64;
+            if !self.improvable(value as f64 + candidate_value + others) {
+                break;
+            }
+
+            if self.budget == 0 {
+                self.exhausted = true;
+                break;
+            }
+            self.budget -= 1;
+
+            let candidate = &self.candidates[index];
+            let rest = stock
+                .iter()
+                .zip(&candidate.usage)
+                .map(|(stock, usage)| stock - count * usage)
+                .collect::<Vec<_>>();
+            let value = value + u128::from(count) * u128::from(candidate.value);
+
+            counts[index] = count;
+            self.explore(index + 1, &rest, value, counts);
+        }
+        counts[index] = 0;
+    }
+}
+
+/// Finds the mix of cookies that maximizes the total value, or the total count, the pantry
+/// supports. Unlike a single recipe, an ingredient missing from the pantry means that recipe
+/// cannot be baked at all.
+pub(super) fn plan(
+    recipes: &HashMap<String, CookieRecipe>,
+    pantry: &HashMap<String, Quantity>,
+    goal: PlanGoal,
+) -> Result<OrderResponse, RecipeError> {
+    let mut names = recipes.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut ingredients = pantry.keys().collect::<Vec<_>>();
+    ingredients.sort();
+
+    // A bare pantry number takes the unit of the first recipe measuring it, so that every
+    // recipe is converted to the same scale.
+    let pinned = ingredients
+        .iter()
+        .map(|&ingredient| {
+            let quantity = pantry[ingredient];
+            let unit = quantity.unit().or_else(|| {
+                names.iter().find_map(|&name| {
+                    recipes[name]
+                        .recipe
+                        .get(ingredient)
+                        .and_then(Quantity::unit)
+                })
+            });
+
+            match unit {
+                Some(unit) => Quantity::Measured {
+                    quantity: quantity.amount(),
+                    unit,
+                },
+                None => quantity,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let stock = ingredients
+        .iter()
+        .zip(&pinned)
+        .map(|(ingredient, quantity)| quantity.base_amount(ingredient, None))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut candidates = Vec::new();
+    'recipes: for &name in &names {
+        let recipe = &recipes[name];
+        let mut usage = vec![0; ingredients.len()];
+        for (ingredient, quantity) in &recipe.recipe {
+            if quantity.amount() == 0 {
+                continue;
+            }
+
+            let Ok(index) = ingredients.binary_search(&ingredient) else {
+                continue 'recipes;
+            };
+            usage[index] = Quantity::common_amounts(ingredient, *quantity, pinned[index])?.0;
+        }
+
+        let value = match goal {
+            PlanGoal::Value => recipe.value,
+            PlanGoal::Count => 1,
+        };
+        if value != 0 && usage.iter().any(|&usage| usage != 0) {
+            candidates.push(Candidate { name, value, usage });
+        }
+    }
+
+    let usage = candidates
+        .iter()
+        .map(|candidate| (candidate.name, candidate.usage.clone()))
+        .collect::<HashMap<_, _>>();
+    let planner = BakePlanner {
+        candidates,
+        budget: SEARCH_LIMIT,
+        exhausted: false,
+        best: None,
+    };
+    let (chosen, optimal) = planner.run(&stock);
+
+    let mut left = stock;
+    let mut cookies = 0u64;
+    let mut value = 0u64;
+    for &(name, count) in &chosen {
+        for (index, amount) in usage[name].iter().enumerate() {
+            left[index] -= checked_mul(ingredients[index], count, *amount)?;
+        }
+
+        let too_many = || RecipeError::InvalidQuantity(format!("Too many {name} cookies"));
+        cookies = cookies.checked_add(count).ok_or_else(too_many)?;
+        value = checked_mul(name, count, recipes[name].value)
+            .ok()
+            .and_then(|total| value.checked_add(total))
+            .ok_or_else(too_many)?;
+    }
+
+    let mut plan = names
+        .iter()
+        .map(|&name| (name.clone(), 0))
+        .collect::<HashMap<_, _>>();
+    plan.extend(
+        chosen
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count)),
+    );
+
+    let pantry = ingredients
+        .iter()
+        .zip(pinned)
+        .zip(left)
+        .map(|((&ingredient, quantity), left)| {
+            (
+                ingredient.clone(),
+                pantry[ingredient].with_amount(left, quantity),
+            )
+        })
+        .collect();
+
+    Ok(OrderResponse {
+        cookies,
+        plan: Some(PlanSummary {
+            plan,
+            value,
+            optimal,
+        }),
+        pantry,
+    })
+}
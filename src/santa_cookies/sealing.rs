@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNED_PREFIX: &str = "v1.s";
+const ENCRYPTED_PREFIX: &str = "v1.e";
+
+#[derive(Clone, Copy, PartialEq)]
+enum SealMode {
+    Plain,
+    Signed,
+    Encrypted,
+}
+
+struct SealKey {
+    id: String,
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+struct SealerConfig {
+    mode: SealMode,
+    keys: Vec<SealKey>,
+    accept_legacy: bool,
+}
+
+/// Signs or encrypts the recipe cookie. The first key seals new cookies while every key
+/// can still open the cookies sealed before a rotation.
+#[derive(Clone)]
+pub struct RecipeSealer(Arc<SealerConfig>);
+
+impl SealKey {
+    fn new(id: &str, secret: &str) -> Result<Self, String> {
+        if id.is_empty() || id.contains('.') {
+            return Err(format!("The cookie key id \"{id}\" is not valid"));
+        }
+
+        let secret = BASE64_STANDARD
+            .decode(secret.trim())
+            .map_err(|_| format!("The cookie key \"{id}\" is not valid base64"))?;
+        if secret.len() < 32 {
+            return Err(format!(
+                "The cookie key \"{id}\" must have at least 32 bytes"
+            ));
+        }
+
+        // Each algorithm gets its own key derived from the configured secret.
+        let derive = |label: &[u8]| -> [u8; 32] {
+            let mut mac =
+                <HmacSha256 as Mac>::new_from_slice(&secret).expect("HMAC accepts any key size");
+            mac.update(label);
+            mac.finalize().into_bytes().into()
+        };
+
+        Ok(Self {
+            id: id.to_string(),
+            signing: derive(b"recipe-cookie-signing"),
+            encryption: derive(b"recipe-cookie-encryption"),
+        })
+    }
+
+    fn mac(&self, content: &str) -> HmacSha256 {
+        let mut mac =
+            <HmacSha256 as Mac>::new_from_slice(&self.signing).expect("HMAC accepts any key size");
+        mac.update(content.as_bytes());
+        mac
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.encryption.into())
+    }
+}
+
+impl RecipeSealer {
+    /// Sealer for deployments without keys, reading and writing plain base64 cookies.
+    pub fn plain() -> Self {
+        Self(Arc::new(SealerConfig {
+            mode: SealMode::Plain,
+            keys: Vec::new(),
+            accept_legacy: true,
+        }))
+    }
+
+    /// Builds the sealer from the `RECIPE_COOKIE_MODE` (`plain`, `signed` or `encrypted`),
+    /// `RECIPE_COOKIE_KEYS` (`id:base64secret` pairs separated by commas, newest first) and
+    /// `RECIPE_COOKIE_ACCEPT_LEGACY` settings.
+    pub fn from_config(
+        mode: Option<&str>,
+        keys: Option<&str>,
+        accept_legacy: Option<&str>,
+    ) -> Result<Self, String> {
+        let mode = match mode.map(str::trim) {
+            None | Some("") | Some("plain") => SealMode::Plain,
+            Some("signed") => SealMode::Signed,
+            Some("encrypted") => SealMode::Encrypted,
+            Some(mode) => return Err(format!("The recipe cookie mode \"{mode}\" is not valid")),
+        };
+
+        let keys = keys
+            .unwrap_or_default()
+            .split(',')
+            .filter(|key| !key.trim().is_empty())
+            .map(|key| match key.trim().split_once(':') {
+                Some((id, secret)) => SealKey::new(id, secret),
+                None => Err("Cookie keys must be written as id:secret".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if mode != SealMode::Plain && keys.is_empty() {
+            return Err("Signed and encrypted recipe cookies need at least one key".into());
+        }
+
+        let accept_legacy = match accept_legacy.map(str::trim) {
+            None | Some("") => mode == SealMode::Plain,
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("\"{value}\" is not a valid boolean"))?,
+        };
+
+        Ok(Self(Arc::new(SealerConfig {
+            mode,
+            keys,
+            accept_legacy,
+        })))
+    }
+
+    /// Returns the sealed cookie value, or `None` when cookies are configured as plain.
+    pub(super) fn seal(&self, raw_recipe: &[u8]) -> Option<String> {
+        let key = self.0.keys.first()?;
+
+        match self.0.mode {
+            SealMode::Plain => None,
+            SealMode::Signed => {
+                let content = format!(
+                    "{SIGNED_PREFIX}.{}.{}",
+                    key.id,
+                    BASE64_URL_SAFE_NO_PAD.encode(raw_recipe)
+                );
+                let tag = key.mac(&content).finalize().into_bytes();
+
+                Some(format!("{content}.{}", BASE64_URL_SAFE_NO_PAD.encode(tag)))
+            }
+            SealMode::Encrypted => {
+                let header = format!("{ENCRYPTED_PREFIX}.{}", key.id);
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let payload = Payload {
+                    msg: raw_recipe,
+                    aad: header.as_bytes(),
+                };
+                let ciphertext = key
+                    .cipher()
+                    .encrypt(&nonce, payload)
+                    .expect("AES-GCM encryption does not fail for cookie sizes");
+
+                Some(format!(
+                    "{header}.{}.{}",
+                    BASE64_URL_SAFE_NO_PAD.encode(nonce),
+                    BASE64_URL_SAFE_NO_PAD.encode(ciphertext)
+                ))
+            }
+        }
+    }
+
+    /// Checks and opens a sealed cookie. `Ok(None)` means the value is a legacy cookie the
+    /// caller has to decode itself.
+    pub(super) fn open(&self, value: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let value = std::str::from_utf8(value).map_err(|_| "The recipe cookie is not valid")?;
+
+        let parts = value.split('.').collect::<Vec<_>>();
+        let (prefix, key_id) = match parts.as_slice() {
+            [version, kind, key_id, _, _] => (format!("{version}.{kind}"), *key_id),
+            _ if self.0.accept_legacy => return Ok(None),
+            _ => return Err("Unsealed recipe cookies are not accepted".into()),
+        };
+
+        let key = self
+            .0
+            .keys
+            .iter()
+            .find(|key| key.id == key_id)
+            .ok_or("The recipe cookie was sealed with an unknown key")?;
+        let tampered = || "The recipe cookie has been tampered with".to_string();
+        let decode = |part: &str| BASE64_URL_SAFE_NO_PAD.decode(part).map_err(|_| tampered());
+
+        match prefix.as_str() {
+            SIGNED_PREFIX => {
+                let content = format!("{prefix}.{key_id}.{}", parts[3]);
+                key.mac(&content)
+                    .verify_slice(&decode(parts[4])?)
+                    .map_err(|_| tampered())?;
+
+                decode(parts[3]).map(Some)
+            }
+            ENCRYPTED_PREFIX => {
+                let header = format!("{prefix}.{key_id}");
+                let nonce = decode(parts[3])?;
+                if nonce.len() != 12 {
+                    return Err(tampered());
+                }
+                let payload = Payload {
+                    msg: &decode(parts[4])?,
+                    aad: header.as_bytes(),
+                };
+
+                key.cipher()
+                    .decrypt(Nonce::from_slice(&nonce), payload)
+                    .map(Some)
+                    .map_err(|_| tampered())
+            }
+            _ => Err("The recipe cookie format is not supported".into()),
+        }
+    }
+}
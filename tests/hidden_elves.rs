@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use axum::{body::Body, http::Request, Router};
+use cch23_demonqilin01::{get_hidden_elves_routes, AppState, PokeApi, PokemonCache, RecipeSealer};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+fn app() -> Router {
+    let state = AppState {
+        pool: PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap(),
+        timekeeper: Arc::new(Mutex::new(HashMap::new())),
+        recipe_sealer: RecipeSealer::plain(),
+        pokemon_cache: PokemonCache::in_memory(),
+        pokeapi: PokeApi::from_config(None, None).unwrap(),
+    };
+
+    Router::new()
+        .nest("/6", get_hidden_elves_routes())
+        .with_state(state)
+}
+
+/// The counts as the endpoint computed them before it streamed its input.
+fn original_counts(body: &str) -> Value {
+    let total_elf = body.matches("elf").count();
+    let total_shelf = body.matches("shelf").count();
+    let total_elf_in_shelf = body
+        .chars()
+        .collect::<Vec<_>>()
+        .windows("elf on a shelf".len())
+        .filter(|slice| slice.iter().collect::<String>() == "elf on a shelf")
+        .count();
+
+    json!({
+        "elf": total_elf,
+        "elf on a shelf": total_elf_in_shelf,
+        "shelf with no elf on it": total_shelf - total_elf_in_shelf,
+    })
+}
+
+/// Sends the body in chunks of `size` bytes, so matches and characters straddle them.
+async fn streamed_counts(body: &str, size: usize) -> Value {
+    let chunks = body
+        .as_bytes()
+        .chunks(size)
+        .map(|chunk| Ok::<_, Infallible>(chunk.to_vec()))
+        .collect::<Vec<_>>();
+    let request = Request::post("/6")
+        .body(Body::wrap_stream(futures_util::stream::iter(chunks)))
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// Builds texts dense in partial matches from a fixed seed.
+fn random_texts() -> Vec<String> {
+    const PIECES: [&str; 12] = [
+        "elf",
+        "shelf",
+        " on a ",
+        "el",
+        "sh",
+        "f",
+        "e",
+        " ",
+        "\n",
+        "é",
+        "elf on a shelf",
+        "on",
+    ];
+
+    let mut seed: u64 = 0x5eed;
+    (0..40)
+        .map(|_| {
+            let mut text = String::new();
+            for _ in 0..60 {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                text.push_str(PIECES[(seed >> 33) as usize % PIECES.len()]);
+            }
+            text
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn streaming_counts_match_the_original_ones() {
+    let mut texts = vec![
+        String::new(),
+        "elelf elfelf shshelf".to_string(),
+        "elf on a shelf on a shelf elf on a shelfelf on a shelf".to_string(),
+        "there is an elf on a shelf on an elf.\n there is also another shelf in Belfast."
+            .to_string(),
+    ];
+    texts.extend(random_texts());
+
+    for text in &texts {
+        for size in [1, 2, 3, 7, 1000] {
+            assert_eq!(
+                streamed_counts(text, size).await,
+                original_counts(text),
+                "{text:?} in chunks of {size}"
+            );
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::Path,
+    http::{header::RETRY_AFTER, HeaderMap, StatusCode},
+    routing::get,
+    Router, Server,
+};
+use cch23_demonqilin01::{get_pokemon_routes, AppState, PokeApi, PokemonCache, RecipeSealer};
+use sqlx::postgres::PgPoolOptions;
+
+const PIKACHU: &str = r#"{"id":25,"name":"pikachu","weight":60,"height":4,
+    "types":[{"slot":1,"type":{"name":"electric"}}],
+    "stats":[{"base_stat":35,"stat":{"name":"hp"}}]}"#;
+
+/// Answers like the PokéAPI would for a handful of ids, each one failing in its own way.
+async fn mock_pokemon(Path(id): Path<String>) -> (StatusCode, HeaderMap, String) {
+    let mut headers = HeaderMap::new();
+    match id.as_str() {
+        "25" => (StatusCode::OK, headers, PIKACHU.to_string()),
+        "1" => {
+            headers.insert(RETRY_AFTER, "30".parse().unwrap());
+            (StatusCode::TOO_MANY_REQUESTS, headers, String::new())
+        }
+        "2" => {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            (StatusCode::OK, headers, PIKACHU.to_string())
+        }
+        "3" => (StatusCode::INTERNAL_SERVER_ERROR, headers, String::new()),
+        "4" => (StatusCode::OK, headers, "not json".to_string()),
+        _ => (StatusCode::NOT_FOUND, headers, "Not Found".to_string()),
+    }
+}
+
+fn serve(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    addr
+}
+
+/// Serves the `/8` routes against a mock PokéAPI, returning the address to call.
+fn spawn_app() -> SocketAddr {
+    let mock = serve(Router::new().route("/pokemon/:id", get(mock_pokemon)));
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+    let state = AppState {
+        pool: PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap(),
+        timekeeper: Arc::new(Mutex::new(HashMap::new())),
+        recipe_sealer: RecipeSealer::plain(),
+        pokemon_cache: PokemonCache::in_memory(),
+        pokeapi: PokeApi::new(client, &format!("http://{mock}")),
+    };
+
+    serve(
+        Router::new()
+            .nest("/8", get_pokemon_routes())
+            .with_state(state),
+    )
+}
+
+#[tokio::test]
+async fn maps_pokeapi_answers_to_statuses() {
+    let app = spawn_app();
+    let weight = |id: &str| reqwest::get(format!("http://{app}/8/weight/{id}"));
+
+    let res = weight("25").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "6");
+
+    assert_eq!(weight("999").await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    let res = weight("1").await.unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers()[RETRY_AFTER], "30");
+
+    let res = weight("2").await.unwrap();
+    assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+
+    assert_eq!(weight("3").await.unwrap().status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(weight("4").await.unwrap().status(), StatusCode::BAD_GATEWAY);
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+};
+use cch23_demonqilin01::{get_reindeer_routes, AppState, PokeApi, PokemonCache, RecipeSealer};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+const FOODS: [&str; 3] = ["hay", "oats", "grass"];
+
+fn app() -> Router {
+    let state = AppState {
+        pool: PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap(),
+        timekeeper: Arc::new(Mutex::new(HashMap::new())),
+        recipe_sealer: RecipeSealer::plain(),
+        pokemon_cache: PokemonCache::in_memory(),
+        pokeapi: PokeApi::from_config(None, None).unwrap(),
+    };
+
+    Router::new()
+        .nest("/4", get_reindeer_routes())
+        .with_state(state)
+}
+
+async fn team(size: usize, request: &Value) -> (StatusCode, Value) {
+    let request = Request::post(format!("/4/team?size={size}"))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(request.to_string()))
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+struct Reindeer {
+    score: u64,
+    candies: u64,
+    food: &'static str,
+}
+
+impl Reindeer {
+    fn to_json(&self, name: usize) -> Value {
+        json!({
+            "name": format!("R{name}"),
+            "strength": self.score,
+            "speed": 0.0,
+            "height": 1,
+            "antler_width": 1,
+            "snow_magic_power": 0,
+            "favorite_food": self.food,
+            "candies_eaten": self.candies,
+        })
+    }
+}
+
+/// Tries every team of `size` reindeer.
+fn brute_force(
+    reindeers: &[Reindeer],
+    size: usize,
+    max_candies: u64,
+    foods: &[&str],
+) -> Option<u64> {
+    (0u32..1 << reindeers.len())
+        .filter(|team| team.count_ones() as usize == size)
+        .map(|team| {
+            reindeers
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| team & (1 << index) != 0)
+                .map(|(_, reindeer)| reindeer)
+                .collect::<Vec<_>>()
+        })
+        .filter(|team| team.iter().map(|reindeer| reindeer.candies).sum::<u64>() <= max_candies)
+        .filter(|team| {
+            foods
+                .iter()
+                .all(|food| team.iter().any(|reindeer| reindeer.food == *food))
+        })
+        .map(|team| team.iter().map(|reindeer| reindeer.score).sum())
+        .max()
+}
+
+#[tokio::test]
+async fn team_search_finds_the_best_team_of_tiny_rosters() {
+    let mut seed: u64 = 0x5eed;
+    let mut random = |below: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % below
+    };
+
+    for _ in 0..100 {
+        let reindeers = (0..7)
+            .map(|_| Reindeer {
+                score: random(20),
+                candies: random(6),
+                food: FOODS[random(3) as usize],
+            })
+            .collect::<Vec<_>>();
+        let size = random(4) as usize + 1;
+        let max_candies = random(15);
+        let foods = &FOODS[..random(3) as usize];
+
+        let request = json!({
+            "reindeer": reindeers
+                .iter()
+                .enumerate()
+                .map(|(name, reindeer)| reindeer.to_json(name))
+                .collect::<Vec<_>>(),
+            "max_candies": max_candies,
+            "required_foods": foods,
+        });
+        let (status, found) = team(size, &request).await;
+
+        match brute_force(&reindeers, size, max_candies, foods) {
+            Some(best) => {
+                assert_eq!(status, StatusCode::OK, "{request}");
+                assert_eq!(found["score"], json!(best as f64), "{request}");
+                assert_eq!(found["optimal"], json!(true), "{request}");
+            }
+            None => assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{request}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn team_search_settles_when_the_search_runs_out() {
+    // The best scores eat the most candies, so the bound keeps promising teams that the candy
+    // limit rules out and the search cannot finish.
+    let reindeers = (0..40)
+        .map(|index| Reindeer {
+            score: 1_000 - index,
+            candies: 40 - index,
+            food: FOODS[index as usize % 3],
+        })
+        .enumerate()
+        .map(|(name, reindeer)| reindeer.to_json(name))
+        .collect::<Vec<_>>();
+    let request = json!({ "reindeer": reindeers, "max_candies": 150 });
+
+    let (status, found) = team(10, &request).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(found["optimal"], json!(false));
+    assert!(found["candies_eaten"].as_u64().unwrap() <= 150);
+    assert_eq!(found["team"].as_array().unwrap().len(), 10);
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request},
+    Router,
+};
+use cch23_demonqilin01::{
+    get_cookies_recipe_routes, AppState, PokeApi, PokemonCache, RecipeSealer,
+};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+fn app() -> Router {
+    let state = AppState {
+        pool: PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap(),
+        timekeeper: Arc::new(Mutex::new(HashMap::new())),
+        recipe_sealer: RecipeSealer::plain(),
+        pokemon_cache: PokemonCache::in_memory(),
+        pokeapi: PokeApi::from_config(None, None).unwrap(),
+    };
+
+    Router::new()
+        .nest("/7", get_cookies_recipe_routes())
+        .with_state(state)
+}
+
+async fn bake(order: Value) -> Value {
+    let request = Request::post("/7/bake")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(order.to_string()))
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert!(response.status().is_success());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// Tries every mix of up to `limit` cookies of each recipe.
+fn brute_force(usage: &[[u64; 2]], values: &[u64], stock: [u64; 2], limit: u64) -> u64 {
+    let mut best = 0;
+    let mut counts = vec![0; usage.len()];
+    loop {
+        let fits = (0..2).all(|ingredient| {
+            let used = counts
+                .iter()
+                .zip(usage)
+                .map(|(count, usage)| count * usage[ingredient])
+                .sum::<u64>();
+            used <= stock[ingredient]
+        });
+        if fits {
+            let value = counts
+                .iter()
+                .zip(values)
+                .map(|(count, value)| count * value);
+            best = best.max(value.sum());
+        }
+
+        let Some(next) = counts.iter().position(|&count| count < limit) else {
+            return best;
+        };
+        counts[next] += 1;
+        counts[..next].fill(0);
+    }
+}
+
+#[tokio::test]
+async fn planner_finds_the_best_mix_of_tiny_pantries() {
+    let mut seed: u64 = 0x5eed;
+    let mut random = |below: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % below
+    };
+
+    for _ in 0..100 {
+        let usage = (0..3)
+            .map(|_| [random(4), random(4) + 1])
+            .collect::<Vec<_>>();
+        let values = (0..3).map(|_| random(10) + 1).collect::<Vec<_>>();
+        let stock = [random(12), random(12)];
+
+        let recipes = (0..3)
+            .map(|recipe| {
+                let [flour, sugar] = usage[recipe];
+                let recipe_json = json!({
+                    "recipe": { "flour": flour, "sugar": sugar },
+                    "value": values[recipe],
+                });
+                (format!("r{recipe}"), recipe_json)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let pantry = json!({ "flour": stock[0], "sugar": stock[1] });
+
+        for (goal, values) in [("value", values.clone()), ("count", vec![1; 3])] {
+            let order = json!({ "recipes": recipes, "pantry": pantry, "maximize": goal });
+            let planned = bake(order.clone()).await;
+
+            let best = brute_force(&usage, &values, stock, 12);
+            let total = match goal {
+                "value" => &planned["value"],
+                _ => &planned["cookies"],
+            };
+            assert_eq!(total, &json!(best), "{order}");
+            assert_eq!(planned["optimal"], json!(true), "{order}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn planner_settles_when_the_search_runs_out() {
+    // Every recipe needs an even amount of flour and is worth exactly that, so no mix reaches
+    // the odd stock the bound promises and nothing can be pruned.
+    let recipes = (0..16)
+        .map(|recipe| {
+            let flour = 2 * (1_000 + 37 * recipe);
+            let recipe_json = json!({ "recipe": { "flour": flour }, "value": flour });
+            (format!("r{recipe:02}"), recipe_json)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let order = json!({ "recipes": recipes, "pantry": { "flour": 1_000_001 } });
+
+    let planned = bake(order).await;
+
+    assert_eq!(planned["optimal"], json!(false));
+    let value = planned["value"].as_u64().unwrap();
+    assert!(value <= 1_000_000);
+    assert_eq!(planned["pantry"]["flour"], json!(1_000_001 - value));
+}